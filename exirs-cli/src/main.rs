@@ -0,0 +1,382 @@
+//! `exirs`, for encoding, decoding and inspecting EXI streams from the command line.
+
+use std::{
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use exirs::{
+    config::{Alignment, Header, Options, Schema},
+    data::{Event, OwnedEvent},
+    trace, xml, Reader, Writer,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "exirs",
+    version,
+    about = "Encode, decode and inspect EXI streams"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encode an XML document as EXI
+    Encode {
+        #[command(flatten)]
+        exi: ExiArgs,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Decode an EXI stream to XML
+    Decode {
+        #[command(flatten)]
+        exi: ExiArgs,
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Print the events of an EXI stream, one per line
+    Dump {
+        #[command(flatten)]
+        exi: ExiArgs,
+        #[command(flatten)]
+        io: IoArgs,
+        /// Show each event's bits and grammar production, as an annotated hex dump
+        #[arg(long)]
+        trace: bool,
+    },
+    /// Print the header of an EXI stream
+    Header {
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Build grammars from XSDs, reporting what they declare
+    Schema {
+        /// The XSDs, as XML or EXI-encoded, which may import one another
+        #[arg(required = true)]
+        xsds: Vec<String>,
+        /// Build the grammars for fragments rather than documents
+        #[arg(long)]
+        fragment: bool,
+    },
+}
+
+#[derive(Args)]
+struct IoArgs {
+    /// The file to read, or standard input if absent or `-`
+    input: Option<PathBuf>,
+    /// The file to write, instead of standard output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// The EXI options to encode with, or to decode streams without options in their headers with
+#[derive(Args, Default)]
+struct ExiArgs {
+    /// An XSD informing the stream, as XML or EXI-encoded, repeated for more than one
+    #[arg(short, long = "schema", value_name = "XSD")]
+    schemas: Vec<String>,
+    /// Allow only what the schema describes, for a smaller stream
+    #[arg(long)]
+    strict: bool,
+    #[arg(long, value_enum, default_value_t = AlignmentArg::BitPacked)]
+    alignment: AlignmentArg,
+    /// Start the stream with `$EXI`
+    #[arg(long)]
+    cookie: bool,
+    /// Leave the options out of the header, so decoders need the same flags
+    #[arg(long)]
+    out_of_band: bool,
+    #[arg(long)]
+    preserve_comments: bool,
+    #[arg(long)]
+    preserve_pis: bool,
+    /// Preserve the DTD and entity references
+    #[arg(long)]
+    preserve_dtd: bool,
+    #[arg(long)]
+    preserve_prefixes: bool,
+    #[arg(long)]
+    preserve_lexical_values: bool,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum AlignmentArg {
+    #[default]
+    BitPacked,
+    ByteAlignment,
+    PreCompression,
+}
+
+impl From<AlignmentArg> for Alignment {
+    fn from(value: AlignmentArg) -> Self {
+        match value {
+            AlignmentArg::BitPacked => Alignment::BitPacked,
+            AlignmentArg::ByteAlignment => Alignment::ByteAlignment,
+            AlignmentArg::PreCompression => Alignment::PreCompression,
+        }
+    }
+}
+
+impl ExiArgs {
+    fn options(&self) -> Options<'static> {
+        Options::default()
+            .strict(self.strict)
+            .alignment(self.alignment.into())
+            .preserve_comments(self.preserve_comments)
+            .preserve_processing_instructions(self.preserve_pis)
+            .preserve_dt_and_er(self.preserve_dtd)
+            .preserve_prefixes(self.preserve_prefixes)
+            .preserve_lexical_values(self.preserve_lexical_values)
+    }
+
+    fn header(&self) -> Header<'static> {
+        let header = match self.out_of_band {
+            true => Header::default(),
+            false => Header::with_options(self.options()),
+        };
+        header.has_cookie(self.cookie)
+    }
+
+    fn schema(&self) -> Result<Option<Schema>, Box<dyn Error>> {
+        if self.schemas.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(load_schema(&self.schemas, self.options())?))
+    }
+
+    fn reader(&self, bytes: Vec<u8>) -> Result<Reader<'static>, Box<dyn Error>> {
+        Ok(Reader::new(bytes, self.schema()?, Some(self.options()))?)
+    }
+}
+
+impl IoArgs {
+    fn read(&self) -> io::Result<Vec<u8>> {
+        match &self.input {
+            Some(path) if path.as_os_str() != "-" => fs::read(path),
+            _ => {
+                let mut bytes = vec![];
+                io::stdin().read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn output(&self) -> io::Result<Box<dyn Write>> {
+        Ok(match &self.output {
+            Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+            None => Box::new(io::stdout().lock()),
+        })
+    }
+}
+
+/// Build grammars from the XSDs at `paths`, encoding any in XML text first
+fn load_schema(paths: &[String], options: Options) -> Result<Schema, Box<dyn Error>> {
+    let mut xsds = Vec::with_capacity(paths.len());
+    for path in paths {
+        let xsd = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        // EXI streams start with `$EXI` or the distinguishing bits `10`, never `<` or a BOM
+        let text = xsd.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&xsd);
+        let xsd = match text.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<') {
+            true => {
+                // EXIP needs prefixes to resolve the type names in attribute values
+                let header = Header::with_options(Options::default().preserve_prefixes(true));
+                xml::encode(xsd.as_slice(), header, None)
+                    .map_err(|e| format!("{}: {}", path, e))?
+                    .to_vec()
+            }
+            false => xsd,
+        };
+        xsds.push(xsd);
+    }
+    let xsds: Vec<&[u8]> = xsds.iter().map(Vec::as_slice).collect();
+    Ok(Schema::from_bytes(&xsds, Some(options)).map_err(|e| e.to_string())?)
+}
+
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Encode { exi, io } => {
+            let xml = io.read()?;
+            let mut writer = Writer::new(exi.header(), exi.schema()?)?;
+            xml::write(xml.as_slice(), &mut writer)?;
+            let mut out = io.output()?;
+            out.write_all(writer.get())?;
+            out.flush()?;
+        }
+        Command::Decode { exi, io } => {
+            let reader = exi.reader(io.read()?)?;
+            let mut out = io.output()?;
+            xml::to_writer(reader, &mut out)?;
+            writeln!(out)?;
+            out.flush()?;
+        }
+        Command::Dump {
+            exi,
+            io,
+            trace: true,
+        } => {
+            let bytes = io.read()?;
+            let mut reader = exi.reader(bytes.clone())?;
+            reader.set_tracing(true);
+            let mut events = vec![];
+            while let Some(event) = reader.next() {
+                let event: OwnedEvent = event?.into();
+                let trace = reader.trace().cloned().ok_or("an event wasn't traced")?;
+                events.push((event, trace));
+            }
+            let mut out = io.output()?;
+            out.write_all(trace::hex_dump(&bytes, &events).as_bytes())?;
+            out.flush()?;
+        }
+        Command::Dump { exi, io, .. } => {
+            let reader = exi.reader(io.read()?)?;
+            let mut out = io.output()?;
+            let mut depth: usize = 0;
+            for event in reader {
+                let event = event?;
+                if event == Event::EndElement {
+                    depth = depth.saturating_sub(1);
+                }
+                writeln!(out, "{:indent$}{:?}", "", event, indent = depth * 2)?;
+                if let Event::StartElement(_) = event {
+                    depth += 1;
+                }
+            }
+            out.flush()?;
+        }
+        Command::Header { io } => {
+            let (header, len) = Header::parse(&io.read()?)?;
+            let mut out = io.output()?;
+            print_header(&mut out, &header, len)?;
+            out.flush()?;
+        }
+        Command::Schema { xsds, fragment } => {
+            let schema = load_schema(&xsds, Options::default().fragment(fragment))?;
+            let elements = schema.elements();
+            println!("{} global elements", elements.len());
+            for element in elements {
+                let namespace = element.name.namespace.unwrap_or_default();
+                println!("  {{{}}}{}", namespace, element.name.local_name);
+            }
+            println!("{} types", schema.types().len());
+        }
+    }
+    Ok(())
+}
+
+fn print_header(out: &mut dyn Write, header: &Header, len: usize) -> io::Result<()> {
+    let yes_no = |is: bool| if is { "yes" } else { "no" };
+    writeln!(out, "length: {} bytes", len)?;
+    writeln!(out, "cookie: {}", yes_no(header.cookie_present()))?;
+    writeln!(
+        out,
+        "version: {}{}",
+        header.version_number(),
+        if header.preview_version() {
+            " (preview)"
+        } else {
+            ""
+        }
+    )?;
+    let Some(options) = header.options() else {
+        return writeln!(out, "options: none, so out of band or the defaults");
+    };
+    writeln!(out, "options:")?;
+    writeln!(out, "  alignment: {:?}", options.get_alignment())?;
+    writeln!(out, "  compression: {}", yes_no(options.is_compressed()))?;
+    writeln!(out, "  strict: {}", yes_no(options.is_strict()))?;
+    writeln!(out, "  fragment: {}", yes_no(options.is_fragment()))?;
+    writeln!(
+        out,
+        "  self-contained: {}",
+        yes_no(options.is_self_contained())
+    )?;
+    writeln!(out, "  schema ID: {:?}", options.get_schema_id_mode())?;
+    if let Some(id) = options.get_schema_id() {
+        writeln!(out, "    {}", id)?;
+    }
+    writeln!(out, "  block size: {}", options.get_blocksize())?;
+    let preserved: Vec<_> = [
+        (options.preserves_comments(), "comments"),
+        (
+            options.preserves_processing_instructions(),
+            "processing instructions",
+        ),
+        (options.preserves_dt_and_er(), "DTD and entity references"),
+        (options.preserves_prefixes(), "prefixes"),
+        (options.preserves_lexical_values(), "lexical values"),
+    ]
+    .into_iter()
+    .filter_map(|(is, name)| is.then_some(name))
+    .collect();
+    match preserved.as_slice() {
+        [] => writeln!(out, "  preserved: nothing"),
+        preserved => writeln!(out, "  preserved: {}", preserved.join(", ")),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("exirs: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[test]
+fn arguments() {
+    use clap::CommandFactory;
+
+    Cli::command().debug_assert();
+    let cli = Cli::try_parse_from([
+        "exirs",
+        "encode",
+        "--schema",
+        "a.exi",
+        "-s",
+        "b.exi",
+        "--strict",
+        "--alignment",
+        "byte-alignment",
+        "--preserve-prefixes",
+        "in.xml",
+        "-o",
+        "out.exi",
+    ])
+    .unwrap();
+    let Command::Encode { exi, io } = cli.command else {
+        panic!("not encode");
+    };
+    assert_eq!(exi.schemas, ["a.exi", "b.exi"]);
+    let options = exi.options();
+    assert!(options.is_strict());
+    assert!(options.preserves_prefixes());
+    assert!(!options.preserves_comments());
+    assert_eq!(options.get_alignment(), Alignment::ByteAlignment);
+    assert!(exi.header().options().is_some());
+    assert_eq!(io.input, Some("in.xml".into()));
+    assert!(Cli::try_parse_from(["exirs", "schema"]).is_err());
+}
+
+#[test]
+fn header_report() {
+    let header = Header::with_options(Options::default().strict(true)).has_cookie(true);
+    let mut writer = Writer::new(header, None).unwrap();
+    writer.document(|d| d.element("a", |_| Ok(()))).unwrap();
+    let (header, len) = Header::parse(writer.get()).unwrap();
+    let mut out = vec![];
+    print_header(&mut out, &header, len).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("cookie: yes"), "{}", out);
+    assert!(out.contains("strict: yes"), "{}", out);
+    assert!(out.contains("preserved: nothing"), "{}", out);
+}
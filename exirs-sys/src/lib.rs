@@ -30,14 +30,18 @@ impl TryFrom<&EXIPDateTime> for chrono::NaiveDateTime {
 
     fn try_from(dt: &EXIPDateTime) -> Result<Self, Self::Error> {
         let tm = dt.dateTime;
+        // Values come straight off the wire, so avoid overflowing on malformed input
         let date = chrono::NaiveDate::from_ymd_opt(
-            tm.tm_year as i32 + 1900,
-            (tm.tm_mon + 1) as u32,
+            (tm.tm_year as i32).checked_add(1900).ok_or(())?,
+            u32::try_from(tm.tm_mon).map_err(|_| ())? + 1,
             tm.tm_mday as u32,
         )
         .ok_or(())?;
         let nanosecs = if (dt.presenceMask & FRACT_PRESENCE as u8) != 0 && dt.fSecs.offset <= 8 {
-            dt.fSecs.value * 10_u32.pow(8 - dt.fSecs.offset as u32)
+            dt.fSecs
+                .value
+                .checked_mul(10_u32.pow(8 - dt.fSecs.offset as u32))
+                .ok_or(())?
         } else {
             0
         };
@@ -0,0 +1,14 @@
+#![no_main]
+
+use exirs::Reader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reader) = Reader::new(data.to_vec(), None, None) {
+        for event in reader {
+            if event.is_err() {
+                break;
+            }
+        }
+    }
+});
@@ -0,0 +1,39 @@
+#![no_main]
+
+use std::cell::RefCell;
+
+use exirs::{
+    config::{Schema, SchemaRegistry},
+    Reader,
+};
+use libfuzzer_sys::fuzz_target;
+
+thread_local! {
+    // Compiling the schema per input would dominate the run time
+    static REGISTRY: RefCell<SchemaRegistry> = RefCell::new({
+        let schema = Schema::new(
+            &[
+                concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/exipe-test-xsd.exi"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/exipe-test-types-xsd.exi"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/exipe-test-nested-xsd.exi"),
+            ],
+            None,
+        )
+        .unwrap();
+        let mut registry = SchemaRegistry::new();
+        registry.set_default(schema);
+        registry
+    });
+}
+
+fuzz_target!(|data: &[u8]| {
+    REGISTRY.with_borrow_mut(|registry| {
+        if let Ok(reader) = Reader::with_registry(data.to_vec(), registry, None) {
+            for event in reader {
+                if event.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+});
@@ -0,0 +1,248 @@
+//! A [tokio-util](https://docs.rs/tokio-util) codec for exchanging documents over a byte stream,
+//! such as a TCP connection or serial link.
+//!
+//! Without framing, documents follow one another directly, and one is decoded once all of it
+//! has arrived. Until then the buffered bytes are parsed again each time more arrive, so
+//! length-delimited framing suits large documents better, and `ExiCodec::max_buffered_length`
+//! bounds how much is buffered.
+
+use std::marker::PhantomData;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+use crate::{
+    config::{Header, Options, Schema},
+    data::OwnedEvent,
+    dom::Document,
+    error::{EXIPError, ErrorKind},
+    Error, Reader, Writer,
+};
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum CodecError {
+    #[error(transparent)]
+    Exi(#[from] Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// More than `ExiCodec::max_buffered_length` bytes arrived without a whole document
+    #[error("{0} bytes buffered without a whole document")]
+    TooLong(usize),
+    /// The stream stopped without `EndDocument`, so the document's length isn't known
+    #[error("the document ended without EndDocument")]
+    Unfinished,
+}
+
+/// A whole document, as sent or received by `ExiCodec`
+pub trait Message: Sized {
+    /// Read the document, from `StartDocument` on
+    fn read(reader: &mut Reader) -> Result<Self, Error>;
+
+    /// Write the document, from `StartDocument` to `EndDocument`
+    fn write(&self, writer: &mut Writer) -> Result<(), Error>;
+}
+
+impl Message for Document {
+    fn read(reader: &mut Reader) -> Result<Self, Error> {
+        Document::read(reader)
+    }
+
+    fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        Document::write(self, writer)
+    }
+}
+
+impl Message for Vec<OwnedEvent> {
+    fn read(reader: &mut Reader) -> Result<Self, Error> {
+        reader.map(|event| event.map(Into::into)).collect()
+    }
+
+    fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        for event in self {
+            writer.add(event.as_event())?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes and encodes documents as `T`, with a schema shared by all of them
+pub struct ExiCodec<T = Document> {
+    header: Header<'static>,
+    schema: Option<Schema>,
+    options: Option<Options<'static>>,
+    framing: Option<LengthDelimitedCodec>,
+    max_length: usize,
+    message: PhantomData<fn() -> T>,
+}
+
+impl<T: Message> ExiCodec<T> {
+    /// Documents are encoded with `header`, and decoded with the options in their own headers
+    pub fn new(header: Header<'static>, schema: Option<Schema>) -> Self {
+        Self {
+            header,
+            schema,
+            options: None,
+            framing: None,
+            max_length: 8 * 1024 * 1024,
+            message: PhantomData,
+        }
+    }
+
+    /// Decode documents without options in their headers with `options`
+    pub fn out_of_band(mut self, options: Options<'static>) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Give up on an unframed document once this many bytes have arrived without all of it,
+    /// 8 MiB by default. Framed documents are limited by the framing's maximum frame length.
+    pub fn max_buffered_length(mut self, max: usize) -> Self {
+        self.max_length = max;
+        self
+    }
+
+    /// Prefix each document with its length, as `framing` does
+    pub fn length_delimited(mut self, framing: LengthDelimitedCodec) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+}
+
+/// Whether `err` is from a document cut short
+fn incomplete(err: &Error) -> bool {
+    *err.kind() == ErrorKind::EXIP(EXIPError::BufferEndReached)
+}
+
+impl<T: Message> Decoder for ExiCodec<T> {
+    type Item = T;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, CodecError> {
+        let bytes = match &mut self.framing {
+            Some(framing) => match framing.decode(src)? {
+                Some(frame) => frame.freeze(),
+                None => return Ok(None),
+            },
+            None if src.is_empty() => return Ok(None),
+            None => Bytes::copy_from_slice(src),
+        };
+        let framed = self.framing.is_some();
+        let reader = match &mut self.schema {
+            Some(schema) => Reader::with_schema(bytes, schema, self.options.clone()),
+            None => Reader::new(bytes, None, self.options.clone()),
+        };
+        let message = reader.and_then(|mut reader| {
+            let message = T::read(&mut reader)?;
+            for event in reader.by_ref() {
+                event?;
+            }
+            Ok((message, reader.consumed()))
+        });
+        match message {
+            Ok((message, consumed)) => {
+                if !framed {
+                    src.advance(consumed.ok_or(CodecError::Unfinished)?);
+                }
+                Ok(Some(message))
+            }
+            Err(e) if !framed && incomplete(&e) => match src.len() >= self.max_length {
+                true => Err(CodecError::TooLong(src.len())),
+                false => Ok(None),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<T: Message> Encoder<T> for ExiCodec<T> {
+    type Error = CodecError;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let header = self.header.clone();
+        let mut writer = match &mut self.schema {
+            Some(schema) => Writer::with_schema(header, schema)?,
+            None => Writer::new(header, None)?,
+        };
+        item.write(&mut writer)?;
+        let bytes = Bytes::copy_from_slice(writer.get());
+        drop(writer);
+        match &mut self.framing {
+            Some(framing) => framing.encode(bytes, dst)?,
+            None => dst.extend_from_slice(&bytes),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn unframed_documents() {
+    use crate::{data::OwnedValue, dom::Element, dom::Node};
+
+    let message = |text: &str| {
+        let mut element = Element::new("message");
+        element
+            .children
+            .push(Node::Value(OwnedValue::String(text.into())));
+        Document::new(element)
+    };
+    let mut codec = ExiCodec::<Document>::new(Header::default(), None);
+    let mut sent = BytesMut::new();
+    codec.encode(message("one"), &mut sent).unwrap();
+    codec.encode(message("two"), &mut sent).unwrap();
+
+    // Arriving a few bytes at a time
+    let mut received = BytesMut::new();
+    let mut decoded = vec![];
+    for chunk in sent.chunks(3) {
+        received.extend_from_slice(chunk);
+        while let Some(document) = codec.decode(&mut received).unwrap() {
+            decoded.push(document);
+        }
+    }
+    assert_eq!(decoded, [message("one"), message("two")]);
+    assert!(received.is_empty());
+
+    // More than the limit without a whole document
+    let mut codec = ExiCodec::<Document>::new(Header::default(), None).max_buffered_length(4);
+    let mut received = BytesMut::from(&sent[..sent.len() / 2]);
+    assert!(received.len() >= 4);
+    assert!(matches!(
+        codec.decode(&mut received),
+        Err(CodecError::TooLong(len)) if len == received.len()
+    ));
+}
+
+#[test]
+fn length_delimited_events() {
+    use crate::data::{OwnedName, OwnedValue};
+    use crate::testing::example_schema;
+
+    let schema = example_schema(None);
+    let flag = |value: bool| {
+        vec![
+            OwnedEvent::StartDocument,
+            OwnedEvent::StartElement(OwnedName {
+                local_name: "bool".into(),
+                namespace: Some("http://www.ltu.se/EISLAB/nested-xsd".into()),
+                prefix: None,
+            }),
+            OwnedEvent::Value(OwnedValue::Boolean(value)),
+            OwnedEvent::EndElement,
+            OwnedEvent::EndDocument,
+        ]
+    };
+    let mut codec = ExiCodec::<Vec<OwnedEvent>>::new(Header::default(), Some(schema))
+        .length_delimited(LengthDelimitedCodec::new());
+    let mut buf = BytesMut::new();
+    codec.encode(flag(true), &mut buf).unwrap();
+    codec.encode(flag(false), &mut buf).unwrap();
+    let len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+
+    let mut partial = buf.split_to(4 + len - 1);
+    assert_eq!(codec.decode(&mut partial).unwrap(), None);
+    partial.extend_from_slice(&buf.split_to(1));
+    assert_eq!(codec.decode(&mut partial).unwrap(), Some(flag(true)));
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(flag(false)));
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+}
@@ -1,21 +1,30 @@
-use std::mem::MaybeUninit;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    mem::MaybeUninit,
+    sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+};
 
 use bitflags::bitflags;
 
-use crate::{data::to_stringtype, error::SchemaError};
+use crate::{
+    data::{from_stringtype, to_stringtype},
+    error::{check, Error, ErrorKind, Operation, SchemaError},
+};
 
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Options<'a> {
     flags: OptionFlags,
     preserve: PreservationFlags,
     schema_id_mode: SchemaIdMode,
-    schema_id: Option<&'a str>,
+    schema_id: Option<Cow<'a, str>>,
     blocksize: u32,
     value_max_length: usize,
     value_partition_capacity: usize,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Alignment {
     BitPacked = 0,
     ByteAlignment = 1,
@@ -33,13 +42,14 @@ impl From<Alignment> for OptionFlags {
 }
 
 impl<'a> Options<'a> {
-    pub(crate) fn ffi(self) -> ffi::EXIOptions {
+    /// The returned options borrow the schema ID, so `self` must outlive them
+    pub(crate) fn ffi(&self) -> ffi::EXIOptions {
         ffi::EXIOptions {
             enumOpt: self.flags.bits(),
             preserve: self.preserve.bits(),
             schemaIDMode: self.schema_id_mode as u32,
             // We repesent no schema ID as Option::None, exip wants an empty string
-            schemaID: to_stringtype(self.schema_id.unwrap_or("")),
+            schemaID: to_stringtype(self.schema_id.as_deref().unwrap_or("")),
             blockSize: self.blocksize,
             valueMaxLength: self.value_max_length,
             valuePartitionCapacity: self.value_partition_capacity,
@@ -49,6 +59,21 @@ impl<'a> Options<'a> {
         }
     }
 
+    /// Copy options out of EXIP, e.g. after parsing a header
+    pub(crate) fn from_ffi(opts: &ffi::EXIOptions) -> Options<'static> {
+        Options {
+            flags: OptionFlags::from_bits_retain(opts.enumOpt),
+            preserve: PreservationFlags::from_bits_retain(opts.preserve),
+            schema_id_mode: SchemaIdMode::from(opts.schemaIDMode),
+            schema_id: from_stringtype(&opts.schemaID)
+                .filter(|id| !id.is_empty())
+                .map(|id| Cow::Owned(id.to_string())),
+            blocksize: opts.blockSize,
+            value_max_length: opts.valueMaxLength,
+            value_partition_capacity: opts.valuePartitionCapacity,
+        }
+    }
+
     pub fn new() -> Options<'a> {
         Self::default()
     }
@@ -110,7 +135,7 @@ impl<'a> Options<'a> {
     }
 
     pub fn schema_id(mut self, id: &'a str) -> Self {
-        self.schema_id = Some(id);
+        self.schema_id = Some(Cow::Borrowed(id));
         self
     }
 
@@ -118,6 +143,75 @@ impl<'a> Options<'a> {
         self.blocksize = blocksize;
         self
     }
+
+    pub fn is_strict(&self) -> bool {
+        self.flags.contains(OptionFlags::STRICT)
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.flags.contains(OptionFlags::FRAGMENT)
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags.contains(OptionFlags::COMPRESSION)
+    }
+
+    pub fn is_self_contained(&self) -> bool {
+        self.flags.contains(OptionFlags::SELF_CONTAINED)
+    }
+
+    pub fn get_alignment(&self) -> Alignment {
+        let alignment = self.flags & OptionFlags::ALIGNMENT;
+        if alignment == OptionFlags::BYTE_ALIGNMENT {
+            Alignment::ByteAlignment
+        } else if alignment == OptionFlags::PRE_COMPRESSION {
+            Alignment::PreCompression
+        } else {
+            Alignment::BitPacked
+        }
+    }
+
+    pub fn preserves_comments(&self) -> bool {
+        self.preserve.contains(PreservationFlags::COMMENTS)
+    }
+
+    pub fn preserves_processing_instructions(&self) -> bool {
+        self.preserve.contains(PreservationFlags::PIS)
+    }
+
+    pub fn preserves_dt_and_er(&self) -> bool {
+        self.preserve.contains(PreservationFlags::DTD)
+    }
+
+    pub fn preserves_prefixes(&self) -> bool {
+        self.preserve.contains(PreservationFlags::PREFIXES)
+    }
+
+    pub fn preserves_lexical_values(&self) -> bool {
+        self.preserve.contains(PreservationFlags::LEXVALUES)
+    }
+
+    pub fn get_schema_id_mode(&self) -> SchemaIdMode {
+        self.schema_id_mode
+    }
+
+    pub fn get_schema_id(&self) -> Option<&str> {
+        self.schema_id.as_deref()
+    }
+
+    pub fn get_blocksize(&self) -> u32 {
+        self.blocksize
+    }
+
+    /// Longest string value added to the string table, `usize::MAX` if unbounded
+    pub fn get_value_max_length(&self) -> usize {
+        self.value_max_length
+    }
+
+    /// Most string values held in the string table, `usize::MAX` if unbounded
+    pub fn get_value_partition_capacity(&self) -> usize {
+        self.value_partition_capacity
+    }
 }
 
 impl<'a> Default for Options<'a> {
@@ -136,7 +230,7 @@ impl<'a> Default for Options<'a> {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     struct OptionFlags: u8 {
         const RESET_ALIGNMENT = 0b00111111;
         const ALIGNMENT = 0xc0;
@@ -152,7 +246,7 @@ bitflags! {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     struct PreservationFlags: u8 {
         const COMMENTS = 0x01;
         const PIS = 0x02;
@@ -163,7 +257,7 @@ bitflags! {
 }
 
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SchemaIdMode {
     Absent,
     Set,
@@ -171,7 +265,18 @@ pub enum SchemaIdMode {
     Empty,
 }
 
-#[derive(Debug)]
+impl From<u32> for SchemaIdMode {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => SchemaIdMode::Set,
+            2 => SchemaIdMode::Nil,
+            3 => SchemaIdMode::Empty,
+            _ => SchemaIdMode::Absent,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Header<'a> {
     has_cookie: bool,
     has_options: bool,
@@ -208,7 +313,68 @@ impl<'a> Header<'a> {
         self
     }
 
-    pub(crate) fn apply(self, ptr: *mut ffi::EXIStream) {
+    /// Parse the header at the start of an EXI stream, without decoding the body.
+    ///
+    /// Also returns the number of bytes the header occupies, including a partially used final byte.
+    pub fn parse(bytes: &[u8]) -> Result<(Header<'static>, usize), Error> {
+        let buf_rep = ffi::BinaryBuffer {
+            buf: bytes.as_ptr() as *mut _,
+            bufLen: bytes.len(),
+            bufContent: bytes.len(),
+            ioStrm: ffi::ioStream {
+                readWriteToStream: None,
+                stream: std::ptr::null_mut(),
+            },
+        };
+        // No content handler is needed, parseHeader never calls it
+        let mut parser = unsafe { Box::<ffi::Parser>::new_zeroed().assume_init() };
+        let ec = unsafe {
+            (ffi::parse.initParser).unwrap()(parser.as_mut(), buf_rep, std::ptr::null_mut())
+        };
+        check(ec, Operation::InitParser)?;
+        let ec = unsafe { (ffi::parse.parseHeader).unwrap()(parser.as_mut(), false as u32) };
+        let out = check(ec, Operation::ParseHeader).map(|_| {
+            let ctx = &parser.strm.context;
+            let len = ctx.bufferIndx + (ctx.bitPointer != 0) as usize;
+            (Header::from_ffi(&parser.strm.header), len)
+        });
+        unsafe { (ffi::parse.destroyParser).unwrap()(parser.as_mut()) };
+        out
+    }
+
+    pub(crate) fn from_ffi(header: &ffi::EXIheader) -> Header<'static> {
+        Header {
+            has_cookie: header.has_cookie != 0,
+            has_options: header.has_options != 0,
+            is_preview_version: header.is_preview_version != 0,
+            version_number: header.version_number,
+            opts: Options::from_ffi(&header.opts),
+        }
+    }
+
+    pub fn cookie_present(&self) -> bool {
+        self.has_cookie
+    }
+
+    pub fn options_present(&self) -> bool {
+        self.has_options
+    }
+
+    pub fn preview_version(&self) -> bool {
+        self.is_preview_version
+    }
+
+    pub fn version_number(&self) -> i16 {
+        self.version_number
+    }
+
+    /// The options carried in the header, if any
+    pub fn options(&self) -> Option<&Options<'a>> {
+        self.has_options.then_some(&self.opts)
+    }
+
+    /// The header's options must outlive the stream's use of them
+    pub(crate) fn apply(&self, ptr: *mut ffi::EXIStream) {
         unsafe {
             (*ptr).header.has_cookie = self.has_cookie as u32;
             (*ptr).header.has_options = self.has_options as u32;
@@ -231,15 +397,74 @@ impl<'a> Default for Header<'a> {
     }
 }
 
+/// Where the options applying to a read stream came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionsSource {
+    /// The stream's header
+    Stream,
+    /// The `Options` supplied to the reader
+    OutOfBand,
+    /// Neither, so the EXI defaults apply
+    Default,
+}
+
+/// A parsed header, along with the options that actually apply to the stream
+#[derive(Clone, Debug)]
+pub struct HeaderInfo {
+    header: Header<'static>,
+    source: OptionsSource,
+}
+
+impl HeaderInfo {
+    pub(crate) fn new(header: &ffi::EXIheader, out_of_band: bool) -> Self {
+        let source = if header.has_options != 0 {
+            OptionsSource::Stream
+        } else if out_of_band {
+            OptionsSource::OutOfBand
+        } else {
+            OptionsSource::Default
+        };
+        Self {
+            header: Header::from_ffi(header),
+            source,
+        }
+    }
+
+    pub fn header(&self) -> &Header<'static> {
+        &self.header
+    }
+
+    /// The effective options, wherever they came from
+    pub fn options(&self) -> &Options<'static> {
+        &self.header.opts
+    }
+
+    pub fn source(&self) -> OptionsSource {
+        self.source
+    }
+}
+
 pub struct Schema {
     pub(crate) inner: Box<ffi::EXIPSchema>,
 }
 
+// SAFETY: `generateSchemaInformedGrammars` allocates everything an `EXIPSchema` points to with
+// `EXIP_MALLOC`, in the schema's own `memList`, which `destroySchema` frees, and EXIP keeps no
+// thread-local or global mutable state for it. Streams reach it only through the `&mut Schema`
+// a reader or writer holds: they add grammars past `staticGrCount`, and entries past the
+// initial string table counts, and remove them when destroyed. So an unused `Schema` owns its
+// allocations as a `Box` would. It isn't `Sync`, as two streams mustn't extend it at once.
+unsafe impl Send for Schema {}
+
 impl Schema {
     /// EXIP sets an arbitrary(?) maximum in it's examples
     /// I assume its just a memory concern?
     const MAX_XSD_FILES_COUNT: usize = 10;
 
+    /// Build the grammars for the EXI-encoded XSDs at `paths`.
+    ///
+    /// Streams with the `fragment` option need a schema built with it, as EXIP builds either
+    /// the document or fragment grammar from `opts`.
     pub fn new<'a>(paths: &'a [&'a str], opts: Option<Options>) -> Result<Schema, SchemaError<'a>> {
         let num_files = paths.len();
 
@@ -247,11 +472,32 @@ impl Schema {
             return Err(SchemaError::TooManyXsds);
         }
 
-        let mut heap_bufs = Vec::with_capacity(num_files);
-        let mut buf_reps = Vec::with_capacity(num_files);
+        let mut xsds = Vec::with_capacity(num_files);
         for path in paths {
-            let mut bytes = std::fs::read(path).map_err(|_| SchemaError::BadFile(path))?;
-            let buf_rep = ffi::BinaryBuffer {
+            xsds.push(std::fs::read(path).map_err(|_| SchemaError::BadFile(path))?);
+        }
+        Self::generate(xsds, opts)
+    }
+
+    /// Build the grammars for EXI-encoded XSDs already in memory
+    pub fn from_bytes(
+        xsds: &[&[u8]],
+        opts: Option<Options>,
+    ) -> Result<Schema, SchemaError<'static>> {
+        if xsds.len() > Self::MAX_XSD_FILES_COUNT {
+            return Err(SchemaError::TooManyXsds);
+        }
+        Self::generate(xsds.iter().map(|xsd| xsd.to_vec()).collect(), opts)
+    }
+
+    fn generate(
+        mut xsds: Vec<Vec<u8>>,
+        opts: Option<Options>,
+    ) -> Result<Schema, SchemaError<'static>> {
+        let num_files = xsds.len();
+        let mut buf_reps: Vec<_> = xsds
+            .iter_mut()
+            .map(|bytes| ffi::BinaryBuffer {
                 buf: bytes.as_mut_ptr() as *mut _,
                 bufLen: bytes.len(),
                 bufContent: bytes.len(),
@@ -259,18 +505,17 @@ impl Schema {
                     readWriteToStream: None,
                     stream: std::ptr::null_mut(),
                 },
-            };
-            heap_bufs.push(bytes);
-            buf_reps.push(buf_rep);
-        }
+            })
+            .collect();
 
         let mut schema: MaybeUninit<ffi::EXIPSchema> = MaybeUninit::uninit();
+        let mut opts = opts.as_ref().map(Options::ffi);
         let ec = unsafe {
             ffi::generateSchemaInformedGrammars(
                 buf_reps.as_mut_ptr(),
                 num_files as u32,
                 ffi::SchemaFormat_SCHEMA_FORMAT_XSD_EXI,
-                opts.map_or(std::ptr::null_mut(), |opts| &mut opts.ffi()),
+                opts.as_mut().map_or(std::ptr::null_mut(), |opts| opts as *mut _),
                 schema.as_mut_ptr(),
                 None,
             )
@@ -284,3 +529,142 @@ impl Schema {
         })
     }
 }
+
+/// A schema either owned by, or lent to, a reader or writer.
+/// EXIP keeps a pointer to it for the lifetime of the stream, and adds to its grammars and
+/// string tables while processing, so it can't be shared between streams.
+pub(crate) enum SchemaRef<'a> {
+    Owned(Schema),
+    Borrowed(&'a mut Schema),
+    Locked(MutexGuard<'a, Schema>),
+}
+
+impl<'a> SchemaRef<'a> {
+    pub(crate) fn as_ptr(&mut self) -> *mut ffi::EXIPSchema {
+        match self {
+            SchemaRef::Owned(s) => s.inner.as_mut(),
+            SchemaRef::Borrowed(s) => s.inner.as_mut(),
+            SchemaRef::Locked(s) => s.inner.as_mut(),
+        }
+    }
+}
+
+/// Schemas keyed by the schema ID a stream declares in its header.
+///
+/// Each schema is locked by the reader using it, so streams with different schemas can be
+/// read at once, while creating a reader for a schema already in use fails with
+/// `ErrorKind::SchemaBusy`.
+#[derive(Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<String, Mutex<Schema>>,
+    default: Option<Mutex<Schema>>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `schema` under `id`, returning any schema previously registered under it
+    pub fn insert(&mut self, id: impl Into<String>, schema: Schema) -> Option<Schema> {
+        self.schemas
+            .insert(id.into(), Mutex::new(schema))
+            .map(|s| s.into_inner().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Schema to use for streams that don't declare a schema ID
+    pub fn set_default(&mut self, schema: Schema) {
+        self.default = Some(Mutex::new(schema));
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.schemas.contains_key(id)
+    }
+
+    /// Pick the schema for a parsed header.
+    /// A `Nil` or `Empty` schema ID means the stream is schemaless, or uses only the built-in types,
+    /// both of which EXIP handles when given no schema.
+    /// The schema is locked until the returned guard is dropped, failing if it already is.
+    pub(crate) fn resolve(
+        &self,
+        header: &ffi::EXIheader,
+    ) -> Result<Option<MutexGuard<'_, Schema>>, Error> {
+        let schema = match SchemaIdMode::from(header.opts.schemaIDMode) {
+            SchemaIdMode::Absent => self.default.as_ref(),
+            SchemaIdMode::Nil | SchemaIdMode::Empty => None,
+            SchemaIdMode::Set => {
+                let id = from_stringtype(&header.opts.schemaID).unwrap_or_default();
+                let schema = self
+                    .schemas
+                    .get(id)
+                    .ok_or_else(|| ErrorKind::UnknownSchemaId(id.to_string()))?;
+                Some(schema)
+            }
+        };
+        schema
+            .map(|s| match s.try_lock() {
+                Ok(schema) => Ok(schema),
+                // A reader that panicked still destroyed its parser, leaving the schema as it was
+                Err(TryLockError::Poisoned(e)) => Ok(e.into_inner()),
+                Err(TryLockError::WouldBlock) => Err(ErrorKind::SchemaBusy.into()),
+            })
+            .transpose()
+    }
+}
+
+#[test]
+fn header_parse() {
+    use crate::{
+        data::{Event, Name},
+        writer::Writer,
+    };
+
+    let options = Options::default()
+        .strict(true)
+        .alignment(Alignment::ByteAlignment)
+        .schema_id_mode(SchemaIdMode::Set)
+        .schema_id("v2");
+    let mut builder = Writer::new(Header::with_options(options).has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    let (header, len) = Header::parse(builder.get()).unwrap();
+    assert!(len > 4 && len < builder.get().len());
+    assert!(header.cookie_present());
+    assert!(!header.preview_version());
+    assert_eq!(header.version_number(), 1);
+    let options = header.options().unwrap();
+    assert!(options.is_strict());
+    assert!(!options.is_fragment());
+    assert_eq!(options.get_alignment(), Alignment::ByteAlignment);
+    assert_eq!(options.get_schema_id_mode(), SchemaIdMode::Set);
+    assert_eq!(options.get_schema_id(), Some("v2"));
+}
+
+#[test]
+fn schema_on_another_thread() {
+    use crate::{
+        reader::Reader,
+        testing::{example_schema, FULL_DOCUMENT},
+    };
+
+    let mut schema = example_schema(None);
+    let events = std::thread::spawn(move || {
+        let reader = Reader::with_schema(FULL_DOCUMENT, &mut schema, None).unwrap();
+        let events = reader.collect::<Result<Vec<_>, _>>().unwrap().len();
+        (schema, events)
+    });
+    let (mut schema, events) = events.join().unwrap();
+    assert!(events > 2);
+    // And back again
+    let reader = Reader::with_schema(FULL_DOCUMENT, &mut schema, None).unwrap();
+    assert_eq!(reader.count(), events);
+}
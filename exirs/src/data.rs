@@ -2,11 +2,11 @@ use std::fmt::Display;
 
 use base64::Engine;
 use bytes::Bytes;
+use chrono::Timelike;
 
 #[derive(Clone, Debug, PartialEq)]
 #[non_exhaustive]
 // No processingInstruction or selfContained support
-
 pub enum Event<'a> {
     StartDocument,
     EndDocument,
@@ -38,47 +38,247 @@ pub struct Name<'a> {
     pub prefix: Option<&'a str>,
 }
 
+/// An unqualified name
+impl<'a> From<&'a str> for Name<'a> {
+    fn from(local_name: &'a str) -> Self {
+        Name {
+            local_name,
+            namespace: None,
+            prefix: None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value<'a> {
     Integer(i64),
     Boolean(bool),
     String(&'a str),
     Float(f64),
+    Decimal(f64),
     Binary(Bytes),
     Timestamp(&'a chrono::NaiveDateTime),
     List(Vec<Value<'a>>),
     QName(Name<'a>),
 }
 
+/// Canonical XML Schema lexical forms: floats as `xs:double`, in exponent form like `1.0E300`;
+/// decimals in full, like `3.0`; timestamps as `xs:dateTime` without trailing zeros in their
+/// fractional seconds; and lists separated by spaces. EXIP's timestamps have already lost any
+/// timezone, so none is written. QNames are written as `prefix:local`, so their namespace must
+/// be declared elsewhere, as the XML writer does. Schemaless writers encode non-string values
+/// as these strings.
 impl<'a> Display for Value<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Integer(int) => write!(f, "{}", int),
             Value::Boolean(bool) => write!(f, "{}", bool),
             Value::String(str) => write!(f, "{}", str),
-            Value::Float(fl) => write!(f, "{}", fl),
+            Value::Float(fl) if fl.is_nan() => write!(f, "NaN"),
+            Value::Float(fl) if fl.is_infinite() => {
+                write!(f, "{}INF", if *fl < 0.0 { "-" } else { "" })
+            }
+            Value::Float(fl) if *fl == 0.0 => {
+                write!(f, "{}0.0E0", if fl.is_sign_negative() { "-" } else { "" })
+            }
+            Value::Float(fl) => {
+                let text = format!("{:e}", fl);
+                let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+                match mantissa.contains('.') {
+                    true => write!(f, "{}E{}", mantissa, exponent),
+                    false => write!(f, "{}.0E{}", mantissa, exponent),
+                }
+            }
+            Value::Decimal(dec) if *dec == 0.0 => write!(f, "0.0"),
+            Value::Decimal(dec) if dec.fract() == 0.0 => write!(f, "{}.0", dec),
+            Value::Decimal(dec) => write!(f, "{}", dec),
             Value::Binary(bin) => write!(
                 f,
                 "{}",
                 base64::engine::general_purpose::STANDARD.encode(bin)
             ),
-            Value::List(list) => write!(
-                f,
-                "{}",
-                list.iter().map(|i| i.to_string()).collect::<String>()
-            ),
-            Value::Timestamp(ts) => write!(f, "{}", ts),
-            Value::QName(qname) => write!(
-                f,
-                "{}{}{}=\"{}\"",
-                qname.prefix.unwrap_or_default(),
-                match qname.prefix {
-                    Some(_) => ":",
-                    None => "",
-                },
-                qname.namespace.unwrap_or_default(),
-                qname.local_name
-            ),
+            Value::List(list) => {
+                for (i, item) in list.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+            Value::Timestamp(ts) => {
+                write!(f, "{}", ts.format("%Y-%m-%dT%H:%M:%S"))?;
+                match ts.nanosecond() % 1_000_000_000 {
+                    0 => Ok(()),
+                    nanos => write!(f, ".{}", format!("{:09}", nanos).trim_end_matches('0')),
+                }
+            }
+            Value::QName(qname) => match qname.prefix {
+                Some(prefix) if !prefix.is_empty() => {
+                    write!(f, "{}:{}", prefix, qname.local_name)
+                }
+                _ => write!(f, "{}", qname.local_name),
+            },
+        }
+    }
+}
+
+/// An `Event` that owns its data, so it can outlive the `Reader` it came from
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum OwnedEvent {
+    StartDocument,
+    EndDocument,
+    StartElement(OwnedName),
+    EndElement,
+    TypeAttribute(OwnedName),
+    Attribute(OwnedAttribute),
+    NamespaceDeclaration(OwnedNamespaceDeclaration),
+    Value(OwnedValue),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedNamespaceDeclaration {
+    pub namespace: String,
+    pub prefix: String,
+    pub is_local_element: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedAttribute {
+    pub key: OwnedName,
+    pub value: OwnedValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OwnedName {
+    pub local_name: String,
+    pub namespace: Option<String>,
+    pub prefix: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OwnedValue {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Float(f64),
+    Decimal(f64),
+    Binary(Bytes),
+    Timestamp(chrono::NaiveDateTime),
+    List(Vec<OwnedValue>),
+    QName(OwnedName),
+}
+
+impl OwnedEvent {
+    pub fn as_event(&self) -> Event<'_> {
+        match self {
+            OwnedEvent::StartDocument => Event::StartDocument,
+            OwnedEvent::EndDocument => Event::EndDocument,
+            OwnedEvent::StartElement(name) => Event::StartElement(name.as_name()),
+            OwnedEvent::EndElement => Event::EndElement,
+            OwnedEvent::TypeAttribute(name) => Event::TypeAttribute(name.as_name()),
+            OwnedEvent::Attribute(attr) => Event::Attribute(Attribute {
+                key: attr.key.as_name(),
+                value: attr.value.as_value(),
+            }),
+            OwnedEvent::NamespaceDeclaration(dec) => {
+                Event::NamespaceDeclaration(dec.as_declaration())
+            }
+            OwnedEvent::Value(value) => Event::Value(value.as_value()),
+        }
+    }
+}
+
+impl OwnedNamespaceDeclaration {
+    pub fn as_declaration(&self) -> NamespaceDeclaration<'_> {
+        NamespaceDeclaration {
+            namespace: &self.namespace,
+            prefix: &self.prefix,
+            is_local_element: self.is_local_element,
+        }
+    }
+}
+
+impl OwnedName {
+    pub fn as_name(&self) -> Name<'_> {
+        Name {
+            local_name: &self.local_name,
+            namespace: self.namespace.as_deref(),
+            prefix: self.prefix.as_deref(),
+        }
+    }
+}
+
+impl OwnedValue {
+    pub fn as_value(&self) -> Value<'_> {
+        match self {
+            OwnedValue::Integer(int) => Value::Integer(*int),
+            OwnedValue::Boolean(bool) => Value::Boolean(*bool),
+            OwnedValue::String(str) => Value::String(str),
+            OwnedValue::Float(fl) => Value::Float(*fl),
+            OwnedValue::Decimal(dec) => Value::Decimal(*dec),
+            OwnedValue::Binary(bin) => Value::Binary(bin.clone()),
+            OwnedValue::Timestamp(ts) => Value::Timestamp(ts),
+            OwnedValue::List(list) => Value::List(list.iter().map(OwnedValue::as_value).collect()),
+            OwnedValue::QName(name) => Value::QName(name.as_name()),
+        }
+    }
+}
+
+impl<'a> From<Event<'a>> for OwnedEvent {
+    fn from(event: Event<'a>) -> Self {
+        match event {
+            Event::StartDocument => OwnedEvent::StartDocument,
+            Event::EndDocument => OwnedEvent::EndDocument,
+            Event::StartElement(name) => OwnedEvent::StartElement(name.into()),
+            Event::EndElement => OwnedEvent::EndElement,
+            Event::TypeAttribute(name) => OwnedEvent::TypeAttribute(name.into()),
+            Event::Attribute(attr) => OwnedEvent::Attribute(OwnedAttribute {
+                key: attr.key.into(),
+                value: attr.value.into(),
+            }),
+            Event::NamespaceDeclaration(dec) => {
+                OwnedEvent::NamespaceDeclaration(OwnedNamespaceDeclaration {
+                    namespace: dec.namespace.to_string(),
+                    prefix: dec.prefix.to_string(),
+                    is_local_element: dec.is_local_element,
+                })
+            }
+            Event::Value(value) => OwnedEvent::Value(value.into()),
+        }
+    }
+}
+
+impl<'a> From<Name<'a>> for OwnedName {
+    fn from(name: Name<'a>) -> Self {
+        OwnedName {
+            local_name: name.local_name.to_string(),
+            namespace: name.namespace.map(str::to_string),
+            prefix: name.prefix.map(str::to_string),
+        }
+    }
+}
+
+/// An unqualified name
+impl From<&str> for OwnedName {
+    fn from(local_name: &str) -> Self {
+        Name::from(local_name).into()
+    }
+}
+
+impl<'a> From<Value<'a>> for OwnedValue {
+    fn from(value: Value<'a>) -> Self {
+        match value {
+            Value::Integer(int) => OwnedValue::Integer(int),
+            Value::Boolean(bool) => OwnedValue::Boolean(bool),
+            Value::String(str) => OwnedValue::String(str.to_string()),
+            Value::Float(fl) => OwnedValue::Float(fl),
+            Value::Decimal(dec) => OwnedValue::Decimal(dec),
+            Value::Binary(bin) => OwnedValue::Binary(bin),
+            Value::Timestamp(ts) => OwnedValue::Timestamp(*ts),
+            Value::List(list) => OwnedValue::List(list.into_iter().map(Into::into).collect()),
+            Value::QName(name) => OwnedValue::QName(name.into()),
         }
     }
 }
@@ -142,3 +342,56 @@ macro_rules! to_qname {
         }
     };
 }
+
+#[test]
+fn display() {
+    let date = chrono::NaiveDate::from_ymd_opt(2012, 7, 31).unwrap();
+    let timestamp = date.and_hms_micro_opt(13, 33, 55, 839).unwrap();
+    let whole = date.and_hms_opt(13, 33, 55).unwrap();
+    let tenths = date.and_hms_milli_opt(13, 33, 55, 500).unwrap();
+    let cases = [
+        (Value::Integer(-42), "-42"),
+        (Value::Boolean(true), "true"),
+        (Value::String("a b"), "a b"),
+        (Value::Float(3.0), "3.0E0"),
+        (Value::Float(-0.25), "-2.5E-1"),
+        (Value::Float(1e300), "1.0E300"),
+        (Value::Float(123.456), "1.23456E2"),
+        (Value::Float(0.0), "0.0E0"),
+        (Value::Float(f64::NAN), "NaN"),
+        (Value::Float(f64::INFINITY), "INF"),
+        (Value::Float(f64::NEG_INFINITY), "-INF"),
+        (Value::Decimal(3.0), "3.0"),
+        (Value::Decimal(-0.25), "-0.25"),
+        (Value::Decimal(-0.0), "0.0"),
+        (
+            Value::Binary(Bytes::from_static(&[0x02, 0x6d, 0x2f])),
+            "Am0v",
+        ),
+        (Value::Timestamp(&timestamp), "2012-07-31T13:33:55.000839"),
+        (Value::Timestamp(&whole), "2012-07-31T13:33:55"),
+        (Value::Timestamp(&tenths), "2012-07-31T13:33:55.5"),
+        (
+            Value::List(vec![Value::Integer(1), Value::Float(2.0)]),
+            "1 2.0E0",
+        ),
+        (Value::List(vec![]), ""),
+        (
+            Value::QName(Name {
+                local_name: "local",
+                namespace: Some("urn:a"),
+                prefix: Some("a"),
+            }),
+            "a:local",
+        ),
+        (Value::QName(Name::from("local")), "local"),
+    ];
+    for (value, text) in cases {
+        assert_eq!(value.to_string(), text, "{:?}", value);
+    }
+    // Canonical forms read back exactly
+    for float in [1e300, -0.25, 0.1, f64::MIN_POSITIVE] {
+        let text = Value::Float(float).to_string();
+        assert_eq!(text.parse::<f64>().unwrap(), float, "{}", text);
+    }
+}
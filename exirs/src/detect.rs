@@ -0,0 +1,144 @@
+//! Recognising EXI streams from their first bytes, and the EXI media type.
+
+/// The media type of EXI streams
+pub const MEDIA_TYPE: &str = "application/exi";
+
+/// The conventional file extension of EXI streams
+pub const FILE_EXTENSION: &str = "exi";
+
+/// The optional cookie at the start of a stream
+pub const COOKIE: &[u8; 4] = b"$EXI";
+
+/// What the start of an EXI header says about the stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Detected {
+    pub has_cookie: bool,
+    pub options_present: bool,
+    pub preview_version: bool,
+    /// 1 for the EXI 1.0 recommendation
+    pub version: u32,
+}
+
+/// Recognise an EXI stream by its cookie, or without one, by the distinguishing bits `10` that
+/// start every header.
+///
+/// Only the first few bytes are read, so versions too large for `Header` aren't recognised.
+/// Without a cookie, any byte from `0x80` to `0xBF` could start a stream, but XML and JSON
+/// text can't begin with one.
+pub fn detect(bytes: &[u8]) -> Option<Detected> {
+    let (has_cookie, rest) = match bytes.strip_prefix(COOKIE) {
+        Some(rest) => (true, rest),
+        None => (false, bytes),
+    };
+    let mut bits = Bits {
+        bytes: rest,
+        pos: 0,
+    };
+    if bits.read(2)? != 0b10 {
+        return None;
+    }
+    let options_present = bits.read(1)? == 1;
+    let preview_version = bits.read(1)? == 1;
+    // Groups of 4 bits, summed, where 15 means another group follows
+    let mut version = 1;
+    loop {
+        let group = bits.read(4)?;
+        version += group;
+        // `Header` can't hold a larger version, and this bounds how far a run of 15s is read
+        if version > i16::MAX as u32 {
+            return None;
+        }
+        if group != 15 {
+            break;
+        }
+    }
+    Some(Detected {
+        has_cookie,
+        options_present,
+        preview_version,
+        version,
+    })
+}
+
+/// Whether a `Content-Type` value is the EXI media type, ignoring any parameters
+pub fn is_exi_media_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default();
+    essence.trim().eq_ignore_ascii_case(MEDIA_TYPE)
+}
+
+/// Reads bits most significant first
+struct Bits<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn read(&mut self, n: usize) -> Option<u32> {
+        let mut out = 0;
+        for _ in 0..n {
+            let byte = self.bytes.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            out = out << 1 | bit as u32;
+            self.pos += 1;
+        }
+        Some(out)
+    }
+}
+
+#[test]
+fn detect_headers() {
+    use crate::{
+        config::{Header, Options},
+        Writer,
+    };
+
+    let header = |header: Header| {
+        let mut writer = Writer::new(header, None).unwrap();
+        writer.document(|d| d.element("a", |_| Ok(()))).unwrap();
+        detect(writer.get())
+    };
+    assert_eq!(
+        header(Header::default()),
+        Some(Detected {
+            has_cookie: false,
+            options_present: false,
+            preview_version: false,
+            version: 1,
+        })
+    );
+    assert_eq!(
+        header(Header::with_options(Options::default().strict(true)).has_cookie(true)),
+        Some(Detected {
+            has_cookie: true,
+            options_present: true,
+            preview_version: false,
+            version: 1,
+        })
+    );
+    // Version 17, in two groups
+    assert_eq!(
+        detect(&[0b1001_1111, 0b0001_0000]).map(|d| (d.preview_version, d.version)),
+        Some((true, 17))
+    );
+
+    assert_eq!(detect(b""), None);
+    assert_eq!(detect(b"$EXI"), None);
+    assert_eq!(detect(b"<?xml version=\"1.0\"?><a/>"), None);
+    assert_eq!(detect(b"{\"a\": 1}"), None);
+    assert_eq!(detect(b"\xEF\xBB\xBF<a/>"), None);
+    // Cut off in the version
+    assert_eq!(detect(&[0b1000_1111]), None);
+    // A version too large for `Header`, however long its groups run on
+    let mut long = vec![0b1000_1111];
+    long.resize(2200, 0xFF);
+    long.push(0);
+    assert_eq!(detect(&long), None);
+}
+
+#[test]
+fn media_types() {
+    assert!(is_exi_media_type("application/exi"));
+    assert!(is_exi_media_type("Application/EXI; charset=binary"));
+    assert!(!is_exi_media_type("application/xml"));
+    assert!(!is_exi_media_type("application/exi+json"));
+}
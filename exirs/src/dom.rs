@@ -0,0 +1,321 @@
+//! An in-memory tree of a document, for when a stream of events isn't convenient.
+
+use crate::{
+    data::{
+        Attribute, Event, OwnedAttribute, OwnedEvent, OwnedName, OwnedNamespaceDeclaration,
+        OwnedValue,
+    },
+    error::SequenceError,
+    Error, Reader, Writer,
+};
+
+/// A document, or with more than one root element, a fragment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    pub elements: Vec<Element>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Element {
+    pub name: OwnedName,
+    /// Declarations made on this element, when prefixes are preserved
+    pub namespaces: Vec<OwnedNamespaceDeclaration>,
+    /// The `xsi:type` of the element
+    pub type_attribute: Option<OwnedName>,
+    pub attributes: Vec<OwnedAttribute>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Value(OwnedValue),
+}
+
+impl Document {
+    pub fn new(root: Element) -> Self {
+        Self {
+            elements: vec![root],
+        }
+    }
+
+    /// Read the rest of the document from `reader`, which must be balanced
+    pub fn read(reader: &mut Reader) -> Result<Self, Error> {
+        Self::build(reader)
+    }
+
+    fn build<'e>(
+        events: impl IntoIterator<Item = Result<Event<'e>, Error>>,
+    ) -> Result<Self, Error> {
+        let mut document = Document::default();
+        let mut open: Vec<Element> = vec![];
+        for (index, event) in events.into_iter().enumerate() {
+            let event: OwnedEvent = event?.into();
+            let outside = |name| Error::new(SequenceError::OutsideElement(name)).at_event(index);
+            match (event, open.last_mut()) {
+                (OwnedEvent::StartElement(name), _) => open.push(Element::new(name)),
+                (OwnedEvent::EndElement, _) => {
+                    let Some(element) = open.pop() else {
+                        return Err(Error::new(SequenceError::UnbalancedEndElement).at_event(index));
+                    };
+                    match open.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => document.elements.push(element),
+                    }
+                }
+                (OwnedEvent::NamespaceDeclaration(ns), Some(element)) => {
+                    element.namespaces.push(ns)
+                }
+                (OwnedEvent::TypeAttribute(name), Some(element)) => {
+                    element.type_attribute = Some(name)
+                }
+                (OwnedEvent::Attribute(attr), Some(element)) => element.attributes.push(attr),
+                (OwnedEvent::Value(value), Some(element)) => {
+                    element.children.push(Node::Value(value))
+                }
+                (OwnedEvent::NamespaceDeclaration(_), None) => {
+                    return Err(outside("NamespaceDeclaration"))
+                }
+                (OwnedEvent::TypeAttribute(_), None) => return Err(outside("TypeAttribute")),
+                (OwnedEvent::Attribute(_), None) => return Err(outside("Attribute")),
+                (OwnedEvent::Value(_), None) => return Err(outside("Value")),
+                (OwnedEvent::StartDocument | OwnedEvent::EndDocument, _) => {}
+            }
+        }
+        // The input ended, perhaps truncated, inside an element
+        if !open.is_empty() {
+            return Err(Error::new(SequenceError::UnclosedElements(open.len())));
+        }
+        Ok(document)
+    }
+
+    /// Write the document to `writer`, from `StartDocument` to `EndDocument`
+    pub fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        writer.add(Event::StartDocument)?;
+        for element in &self.elements {
+            element.write(writer)?;
+        }
+        writer.add(Event::EndDocument)
+    }
+
+    /// The first root element
+    pub fn root(&self) -> Option<&Element> {
+        self.elements.first()
+    }
+
+    pub fn root_mut(&mut self) -> Option<&mut Element> {
+        self.elements.first_mut()
+    }
+}
+
+impl Element {
+    pub fn new(name: impl Into<OwnedName>) -> Self {
+        Self {
+            name: name.into(),
+            namespaces: vec![],
+            type_attribute: None,
+            attributes: vec![],
+            children: vec![],
+        }
+    }
+
+    /// The value of the first attribute with this local name
+    pub fn attribute(&self, local_name: &str) -> Option<&OwnedValue> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.key.local_name == local_name)
+            .map(|attr| &attr.value)
+    }
+
+    /// Child elements, skipping values
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Value(_) => None,
+        })
+    }
+
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Value(_) => None,
+        })
+    }
+
+    /// The first child element with this local name
+    pub fn child(&self, local_name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name.local_name == local_name)
+    }
+
+    pub fn child_mut(&mut self, local_name: &str) -> Option<&mut Element> {
+        self.elements_mut()
+            .find(|e| e.name.local_name == local_name)
+    }
+
+    /// Values directly inside this element
+    pub fn values(&self) -> impl Iterator<Item = &OwnedValue> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Value(value) => Some(value),
+            Node::Element(_) => None,
+        })
+    }
+
+    /// The first value directly inside this element
+    pub fn value(&self) -> Option<&OwnedValue> {
+        self.values().next()
+    }
+
+    /// Write the element and its descendants to `writer`
+    pub fn write(&self, writer: &mut Writer) -> Result<(), Error> {
+        writer.add(Event::StartElement(self.name.as_name()))?;
+        for ns in &self.namespaces {
+            writer.add(Event::NamespaceDeclaration(ns.as_declaration()))?;
+        }
+        if let Some(name) = &self.type_attribute {
+            writer.add(Event::TypeAttribute(name.as_name()))?;
+        }
+        for attr in &self.attributes {
+            writer.add(Event::Attribute(Attribute {
+                key: attr.key.as_name(),
+                value: attr.value.as_value(),
+            }))?;
+        }
+        for child in &self.children {
+            match child {
+                Node::Element(element) => element.write(writer)?,
+                Node::Value(value) => writer.add(Event::Value(value.as_value()))?,
+            }
+        }
+        writer.add(Event::EndElement)
+    }
+}
+
+#[test]
+fn schemaless_round_trip() {
+    use crate::config::Header;
+    use crate::data::Value;
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("order", |e| {
+                e.attr("id", Value::String("7"))?;
+                e.element("customer", |e| e.text("Ada"))?;
+                e.element("item", |e| {
+                    e.attr("sku", Value::String("A1"))?;
+                    e.text("2")
+                })?;
+                e.element("item", |e| e.attr("sku", Value::String("B2")))
+            })
+        })
+        .unwrap();
+    let bytes = writer.get().to_vec();
+
+    let document = Document::read(&mut Reader::new(bytes.clone(), None, None).unwrap()).unwrap();
+    let root = document.root().unwrap();
+    assert_eq!(root.name.local_name, "order");
+    assert_eq!(root.attribute("id"), Some(&OwnedValue::String("7".into())));
+    assert_eq!(
+        root.child("customer").unwrap().value(),
+        Some(&OwnedValue::String("Ada".into()))
+    );
+    let items: Vec<_> = root
+        .elements()
+        .filter(|e| e.name.local_name == "item")
+        .collect();
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        items[1].attribute("sku"),
+        Some(&OwnedValue::String("B2".into()))
+    );
+    assert!(items[1].children.is_empty());
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    document.write(&mut writer).unwrap();
+    assert_eq!(writer.get(), bytes);
+}
+
+#[test]
+fn schema_round_trip() {
+    use crate::config::{Header, Options};
+    use crate::testing::{example_document, example_schema};
+
+    let schema = || example_schema(None);
+    let header = || Header::with_options(Options::default().strict(true));
+    let bytes = example_document();
+
+    let mut document =
+        Document::read(&mut Reader::new(bytes.clone(), Some(schema()), None).unwrap()).unwrap();
+    let root = document.root().unwrap();
+    assert_eq!(
+        root.child("EXIPEncoder").unwrap().attribute("testByte"),
+        Some(&OwnedValue::Integer(55))
+    );
+    assert_eq!(
+        root.child("type-test")
+            .unwrap()
+            .child("bool")
+            .unwrap()
+            .value(),
+        Some(&OwnedValue::Boolean(true))
+    );
+
+    let mut writer = Writer::new(header(), Some(schema())).unwrap();
+    document.write(&mut writer).unwrap();
+    assert_eq!(writer.get(), bytes);
+
+    // Edits are written back with their types
+    let byte = document
+        .root_mut()
+        .unwrap()
+        .child_mut("extendedTypeTest")
+        .unwrap()
+        .child_mut("byteTest")
+        .unwrap();
+    byte.children = vec![Node::Value(OwnedValue::Integer(12))];
+    let mut writer = Writer::new(header(), Some(schema())).unwrap();
+    document.write(&mut writer).unwrap();
+    let edited =
+        Document::read(&mut Reader::new(writer.get().to_vec(), Some(schema()), None).unwrap())
+            .unwrap();
+    assert_eq!(edited, document);
+}
+
+#[test]
+fn unbalanced() {
+    use crate::{data::Value, error::ErrorKind};
+
+    let kind = |events: Vec<Event<'static>>| {
+        let err = Document::build(events.into_iter().map(Ok)).unwrap_err();
+        err.kind().clone()
+    };
+    let start = || Event::StartElement("a".into());
+    assert_eq!(
+        kind(vec![Event::StartDocument, Event::EndElement]),
+        ErrorKind::Sequence(SequenceError::UnbalancedEndElement)
+    );
+    assert_eq!(
+        kind(vec![Event::StartDocument, Event::Value(Value::String("x"))]),
+        ErrorKind::Sequence(SequenceError::OutsideElement("Value"))
+    );
+    assert_eq!(
+        kind(vec![
+            Event::StartDocument,
+            start(),
+            start(),
+            Event::EndElement
+        ]),
+        ErrorKind::Sequence(SequenceError::UnclosedElements(1))
+    );
+    let document = Document::build(
+        [
+            Event::StartDocument,
+            start(),
+            Event::EndElement,
+            Event::EndDocument,
+        ]
+        .map(Ok),
+    )
+    .unwrap();
+    assert_eq!(document, Document::new(Element::new("a")));
+}
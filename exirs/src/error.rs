@@ -1,3 +1,7 @@
+use std::fmt::Display;
+
+use crate::schema::TypeClass;
+
 #[derive(thiserror::Error, Clone, Debug, PartialEq)]
 pub enum EXIPError {
     #[error("unimplemented in EXIP")]
@@ -32,9 +36,9 @@ pub enum EXIPError {
     HeaderOptionsMismatch = 16,
 }
 
-impl From<u32> for EXIPError {
-    fn from(value: u32) -> Self {
-        match value {
+impl EXIPError {
+    pub fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
             1 => EXIPError::NotImplemented,
             2 => EXIPError::Unexpected,
             3 => EXIPError::HashTable,
@@ -50,8 +54,14 @@ impl From<u32> for EXIPError {
             14 => EXIPError::NoPrefixesPreservedXMLSchema,
             15 => EXIPError::InvalidStringOperation,
             16 => EXIPError::HeaderOptionsMismatch,
-            _ => EXIPError::Unexpected,
-        }
+            _ => return None,
+        })
+    }
+}
+
+impl From<u32> for EXIPError {
+    fn from(value: u32) -> Self {
+        EXIPError::from_code(value).unwrap_or(EXIPError::Unexpected)
     }
 }
 
@@ -72,3 +82,271 @@ pub enum SchemaError<'a> {
     #[error("failed generating grammars")]
     GramGenFail,
 }
+
+/// The EXIP function that failed, named as in EXIP
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    InitParser,
+    ParseHeader,
+    SetSchema,
+    ParseNext,
+    InitStream,
+    ExiHeader,
+    StartDocument,
+    EndDocument,
+    StartElement,
+    EndElement,
+    Attribute,
+    IntData,
+    BooleanData,
+    StringData,
+    FloatData,
+    BinaryData,
+    DateTimeData,
+    ListData,
+    QNameData,
+    NamespaceDeclaration,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Operation::InitParser => "initParser",
+            Operation::ParseHeader => "parseHeader",
+            Operation::SetSchema => "setSchema",
+            Operation::ParseNext => "parseNext",
+            Operation::InitStream => "initStream",
+            Operation::ExiHeader => "exiHeader",
+            Operation::StartDocument => "startDocument",
+            Operation::EndDocument => "endDocument",
+            Operation::StartElement => "startElement",
+            Operation::EndElement => "endElement",
+            Operation::Attribute => "attribute",
+            Operation::IntData => "intData",
+            Operation::BooleanData => "booleanData",
+            Operation::StringData => "stringData",
+            Operation::FloatData => "floatData",
+            Operation::BinaryData => "binaryData",
+            Operation::DateTimeData => "dateTimeData",
+            Operation::ListData => "listData",
+            Operation::QNameData => "qnameData",
+            Operation::NamespaceDeclaration => "namespaceDeclaration",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error(transparent)]
+    EXIP(#[from] EXIPError),
+    #[error("unknown EXIP error code {0}")]
+    UnknownCode(u32),
+    #[error("no schema registered for schema ID `{0}`")]
+    UnknownSchemaId(String),
+    #[error("the schema for this stream is in use by another reader")]
+    SchemaBusy,
+    #[error(transparent)]
+    Sequence(#[from] SequenceError),
+    #[error("`{0}` is not a valid lexical {1:?} value")]
+    InvalidLexical(String, TypeClass),
+    #[error("invalid path `{0}`: {1}")]
+    InvalidPath(String, &'static str),
+}
+
+/// An event somewhere it can't appear, added to a `Writer` or read into a `dom::Document`
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum SequenceError {
+    #[error("{0} before StartDocument")]
+    BeforeStartDocument(&'static str),
+    #[error("{0} after EndDocument")]
+    AfterEndDocument(&'static str),
+    #[error("StartDocument after the document has started")]
+    DuplicateStartDocument,
+    #[error("EndElement with no open element")]
+    UnbalancedEndElement,
+    #[error("EndDocument with {0} unclosed element(s)")]
+    UnclosedElements(usize),
+    #[error("EndDocument without a root element")]
+    NoRootElement,
+    #[error("a second root element, outside of a fragment")]
+    MultipleRootElements,
+    #[error("{0} outside of an element")]
+    OutsideElement(&'static str),
+    #[error("{0} after content")]
+    AfterContent(&'static str),
+}
+
+/// An error from reading or writing a stream, with where in the stream it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    kind: ErrorKind,
+    operation: Option<Operation>,
+    event: Option<usize>,
+    path: Option<String>,
+    bit_offset: Option<usize>,
+}
+
+impl Error {
+    pub(crate) fn new(kind: impl Into<ErrorKind>) -> Self {
+        Self {
+            kind: kind.into(),
+            operation: None,
+            event: None,
+            path: None,
+            bit_offset: None,
+        }
+    }
+
+    pub(crate) fn from_code(code: u32) -> Self {
+        match EXIPError::from_code(code) {
+            Some(e) => Self::new(e),
+            None => Self::new(ErrorKind::UnknownCode(code)),
+        }
+    }
+
+    pub(crate) fn during(mut self, op: Operation) -> Self {
+        self.operation = Some(op);
+        self
+    }
+
+    pub(crate) fn at_event(mut self, index: usize) -> Self {
+        self.event = Some(index);
+        self
+    }
+
+    pub(crate) fn at_path(mut self, path: String) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    pub(crate) fn at_bit(mut self, offset: usize) -> Self {
+        self.bit_offset = Some(offset);
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The EXIP error code, if the error came from EXIP
+    pub fn code(&self) -> Option<u32> {
+        match &self.kind {
+            ErrorKind::EXIP(e) => Some(e.clone().into()),
+            ErrorKind::UnknownCode(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn operation(&self) -> Option<Operation> {
+        self.operation
+    }
+
+    /// Zero-based index of the event being read or written
+    pub fn event_index(&self) -> Option<usize> {
+        self.event
+    }
+
+    /// Local names of the open elements, e.g. `/MultipleXSDsTest/EXIPEncoder/@testByte`
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn bit_offset(&self) -> Option<usize> {
+        self.bit_offset
+    }
+
+    pub fn byte_offset(&self) -> Option<usize> {
+        self.bit_offset.map(|bits| bits / 8)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(op) = self.operation {
+            write!(f, " in `{}`", op)?;
+        }
+        if let Some(event) = self.event {
+            write!(f, " at event {}", event)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path)?;
+        }
+        if let Some(bits) = self.bit_offset {
+            write!(f, ", bit offset {} (byte {})", bits, bits / 8)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+impl From<EXIPError> for Error {
+    fn from(value: EXIPError) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<SequenceError> for Error {
+    fn from(value: SequenceError) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(value: ErrorKind) -> Self {
+        Self::new(value)
+    }
+}
+
+/// How serious a diagnostic reported by EXIP is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    /// Recoverable, parsing continues
+    Error,
+    Fatal,
+}
+
+/// A warning or error reported by EXIP while parsing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: u32,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(&self) -> Option<EXIPError> {
+        EXIPError::from_code(self.code)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal error",
+        };
+        write!(f, "EXIP {} (code {})", severity, self.code)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Turn an EXIP return code into a `Result`
+pub(crate) fn check(ec: u32, op: Operation) -> Result<(), Error> {
+    match ec {
+        0 => Ok(()),
+        e => Err(Error::from_code(e).during(op)),
+    }
+}
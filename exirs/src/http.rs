@@ -0,0 +1,409 @@
+//! A [tower](https://docs.rs/tower) layer letting HTTP services that speak XML exchange EXI
+//! with their clients instead.
+//!
+//! Request bodies of type `application/exi` are decoded to XML, or with `ExiLayer::events`, to
+//! a `Vec<OwnedEvent>` in the request's extensions. XML responses are encoded when the
+//! request's `Accept` header prefers `application/exi`. Other requests and responses pass
+//! through untouched, and bodies that can't be decoded are answered with `400 Bad Request`.
+//! XML responses get `Vary: accept`, whether they were encoded or not.
+//!
+//! Each body needs a schema to itself, so with one schema requests are handled one at a time,
+//! the others waiting for it without blocking; `ExiLayer::with_schemas` lends out a pool of
+//! them. Once it has a schema, a body is encoded or decoded in one go in the response future.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
+    task::{Context, Poll},
+};
+
+use ::http::{header, request, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use bytes::Bytes;
+use http_body::Body;
+use http_body_util::{BodyExt, Either, Full};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    config::{Header, Schema},
+    data::OwnedEvent,
+    detect::{is_exi_media_type, MEDIA_TYPE},
+    xml::{self, XmlError},
+    Reader, Writer,
+};
+
+/// Wraps services with `ExiService`
+#[derive(Clone)]
+pub struct ExiLayer {
+    config: Config,
+}
+
+#[derive(Clone)]
+struct Config {
+    header: Header<'static>,
+    /// Each lent to one request or response at a time
+    schemas: Arc<[Mutex<Schema>]>,
+    /// One for each schema not lent out
+    free: Arc<Semaphore>,
+    events: bool,
+}
+
+/// A schema lent from the pool, until it's dropped
+struct Lent<'a> {
+    schema: MutexGuard<'a, Schema>,
+    // Released once the schema is unlocked, so whoever acquires it finds a schema free
+    _permit: SemaphorePermit<'a>,
+}
+
+impl ExiLayer {
+    /// Responses are encoded with `header`, and requests and responses with `schema`, which is
+    /// shared by all of them
+    pub fn new(header: Header<'static>, schema: Option<Schema>) -> Self {
+        Self::with_schemas(header, schema)
+    }
+
+    /// Like `new`, but with a pool of schemas built from the same XSDs, so that as many
+    /// requests and responses can be encoded or decoded at once
+    pub fn with_schemas(
+        header: Header<'static>,
+        schemas: impl IntoIterator<Item = Schema>,
+    ) -> Self {
+        let schemas: Arc<[_]> = schemas.into_iter().map(Mutex::new).collect();
+        Self {
+            config: Config {
+                header,
+                free: Arc::new(Semaphore::new(schemas.len())),
+                schemas,
+                events: false,
+            },
+        }
+    }
+
+    /// Decode requests to a `Vec<OwnedEvent>` in their extensions, leaving their bodies empty
+    pub fn events(mut self) -> Self {
+        self.config.events = true;
+        self
+    }
+}
+
+impl<S> Layer<S> for ExiLayer {
+    type Service = ExiService<S>;
+
+    fn layer(&self, inner: S) -> ExiService<S> {
+        ExiService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExiService<S> {
+    inner: S,
+    config: Config,
+}
+
+impl Config {
+    /// A schema from the pool, waiting for one if they're all in use
+    async fn schema(&self) -> Option<Lent<'_>> {
+        if self.schemas.is_empty() {
+            return None;
+        }
+        let permit = self
+            .free
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        let schema = self
+            .schemas
+            .iter()
+            .find_map(|schema| match schema.try_lock() {
+                Ok(schema) => Some(schema),
+                // A body that panicked still destroyed its reader or writer
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            });
+        Some(Lent {
+            schema: schema.expect("a schema is free for each permit"),
+            _permit: permit,
+        })
+    }
+
+    /// Decode an EXI request body, updating its headers to match
+    async fn decode(&self, parts: &mut request::Parts, body: Bytes) -> Result<Bytes, XmlError> {
+        let mut lent = self.schema().await;
+        let reader = match &mut lent {
+            Some(lent) => Reader::with_schema(body, &mut lent.schema, None)?,
+            None => Reader::new(body, None, None)?,
+        };
+        parts.headers.remove(header::CONTENT_LENGTH);
+        if self.events {
+            let events: Vec<OwnedEvent> = reader
+                .map(|event| event.map(Into::into))
+                .collect::<Result<_, _>>()?;
+            parts.extensions.insert(events);
+            parts.headers.remove(header::CONTENT_TYPE);
+            Ok(Bytes::new())
+        } else {
+            let mut out = vec![];
+            xml::to_writer(reader, &mut out)?;
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/xml"),
+            );
+            Ok(out.into())
+        }
+    }
+
+    async fn encode(&self, body: &[u8]) -> Result<Bytes, XmlError> {
+        let mut lent = self.schema().await;
+        let mut writer = match &mut lent {
+            Some(lent) => Writer::with_schema(self.header.clone(), &mut lent.schema)?,
+            None => Writer::new(self.header.clone(), None)?,
+        };
+        xml::write(body, &mut writer)?;
+        Ok(Bytes::copy_from_slice(writer.get()))
+    }
+}
+
+impl<S, B, R> Service<Request<B>> for ExiService<S>
+where
+    S: Service<Request<B>, Response = Response<R>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Body + From<Bytes> + Send + 'static,
+    B::Data: Send,
+    B::Error: std::fmt::Display,
+    R: Body + Send + 'static,
+    R::Data: Send,
+    R::Error: std::fmt::Display,
+{
+    type Response = Response<Either<Full<Bytes>, R>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // The service that was polled ready handles this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        Box::pin(async move {
+            let accepts_exi = req
+                .headers()
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .is_some_and(prefers_exi);
+            let req = if has_type(req.headers(), is_exi_media_type) {
+                let (mut parts, body) = req.into_parts();
+                let body = match body.collect().await {
+                    Ok(body) => body.to_bytes(),
+                    Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+                };
+                match config.decode(&mut parts, body).await {
+                    Ok(body) => Request::from_parts(parts, B::from(body)),
+                    Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e)),
+                }
+            } else {
+                req
+            };
+
+            let res = inner.call(req).await?;
+            if !has_type(res.headers(), is_xml_media_type) {
+                return Ok(res.map(Either::Right));
+            }
+            let (mut parts, body) = res.into_parts();
+            // Whether the body is encoded depends on `Accept`
+            parts
+                .headers
+                .append(header::VARY, HeaderValue::from_static("accept"));
+            if !accepts_exi {
+                return Ok(Response::from_parts(parts, Either::Right(body)));
+            }
+            let body = match body.collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(e) => return Ok(error(StatusCode::INTERNAL_SERVER_ERROR, e)),
+            };
+            let body = match config.encode(&body).await {
+                Ok(body) => body,
+                Err(e) => return Ok(error(StatusCode::INTERNAL_SERVER_ERROR, e)),
+            };
+            parts
+                .headers
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(MEDIA_TYPE));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, Either::Left(Full::new(body))))
+        })
+    }
+}
+
+/// A plain text response describing `err`
+fn error<R>(status: StatusCode, err: impl std::fmt::Display) -> Response<Either<Full<Bytes>, R>> {
+    let mut res = Response::new(Either::Left(Full::new(Bytes::from(err.to_string()))));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    res
+}
+
+fn has_type(headers: &HeaderMap, is: fn(&str) -> bool) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(is)
+}
+
+fn is_xml_media_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    ["application/xml", "text/xml"]
+        .iter()
+        .any(|xml| essence.eq_ignore_ascii_case(xml))
+        || essence.to_ascii_lowercase().ends_with("+xml")
+}
+
+/// Whether `application/exi` has the highest quality of the media ranges in an `Accept` header
+fn prefers_exi(accept: &str) -> bool {
+    let (mut exi, mut others) = (0.0, 0.0);
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media_type = params.next().unwrap_or_default();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if is_exi_media_type(media_type) {
+            exi = f32::max(exi, quality);
+        } else {
+            others = f32::max(others, quality);
+        }
+    }
+    exi > 0.0 && exi >= others
+}
+
+#[test]
+fn accept_headers() {
+    assert!(prefers_exi("application/exi"));
+    assert!(prefers_exi("application/xml;q=0.9, application/exi"));
+    assert!(prefers_exi("application/exi, */*"));
+    assert!(!prefers_exi("application/xml, application/exi;q=0.5"));
+    assert!(!prefers_exi("application/exi;q=0"));
+    assert!(!prefers_exi("*/*"));
+}
+
+#[cfg(test)]
+async fn echo(
+    req: Request<Full<Bytes>>,
+) -> Result<Response<Full<Bytes>>, std::convert::Infallible> {
+    let content_type = req.headers().get(header::CONTENT_TYPE).cloned();
+    let events = req.extensions().get::<Vec<OwnedEvent>>().map(Vec::len);
+    let body = req.into_body().collect().await?.to_bytes();
+    let mut res = Response::new(Full::new(match events {
+        Some(events) => Bytes::from(format!("{} events", events)),
+        None => body,
+    }));
+    if let Some(content_type) = content_type {
+        res.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn negotiate() {
+    use tower::ServiceExt;
+
+    let service = ExiLayer::new(Header::default(), None).layer(tower::service_fn(echo));
+    let exi = xml::encode(&b"<note>hi</note>"[..], Header::default(), None).unwrap();
+    let request = |content_type: &'static str, accept: &'static str, body: Bytes| {
+        Request::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::ACCEPT, accept)
+            .body(Full::new(body))
+            .unwrap()
+    };
+    let body = |res: Response<Either<Full<Bytes>, Full<Bytes>>>| async move {
+        res.into_body().collect().await.unwrap().to_bytes()
+    };
+
+    // EXI both ways
+    let res = service
+        .clone()
+        .oneshot(request(MEDIA_TYPE, MEDIA_TYPE, exi.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()[header::CONTENT_TYPE], MEDIA_TYPE);
+    assert_eq!(res.headers()[header::VARY], "accept");
+    let exi = body(res).await;
+    let xml = xml::decode(Reader::new(exi, None, None).unwrap()).unwrap();
+    assert!(xml.ends_with("<note>hi</note>"), "{}", xml);
+
+    // XML both ways
+    let res = service
+        .clone()
+        .oneshot(request(
+            "application/xml",
+            "application/xml",
+            "<note/>".into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(res.headers()[header::CONTENT_TYPE], "application/xml");
+    assert_eq!(res.headers()[header::VARY], "accept");
+    assert_eq!(body(res).await, "<note/>");
+
+    // Not EXI after all
+    let res = service
+        .clone()
+        .oneshot(request(MEDIA_TYPE, MEDIA_TYPE, "<note/>".into()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // Decoded to events
+    let service = ExiLayer::new(Header::default(), None)
+        .events()
+        .layer(tower::service_fn(echo));
+    let exi = xml::encode(&b"<note>hi</note>"[..], Header::default(), None).unwrap();
+    let res = service
+        .oneshot(request(MEDIA_TYPE, "text/plain", exi))
+        .await
+        .unwrap();
+    assert!(!res.headers().contains_key(header::VARY));
+    assert_eq!(body(res).await, "5 events");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn schema_pool() {
+    use crate::testing::example_schema;
+
+    let schema = || example_schema(None);
+    let layer = ExiLayer::with_schemas(Header::default(), [schema(), schema()]);
+    // Both can be lent at once
+    let first = layer.config.schema().await.unwrap();
+    let second = layer.config.schema().await.unwrap();
+    assert!(!std::ptr::eq(&*first.schema, &*second.schema));
+    // Then a third waits, without blocking, until one is returned
+    let third = layer.config.schema();
+    tokio::pin!(third);
+    tokio::select! {
+        biased;
+        _ = &mut third => panic!("lent a schema already in use"),
+        _ = std::future::ready(()) => {}
+    }
+    let returned: *const Schema = &*first.schema;
+    drop(first);
+    assert!(std::ptr::eq(&*third.await.unwrap().schema, returned));
+    drop(second);
+    assert!(ExiLayer::new(Header::default(), None)
+        .config
+        .schema()
+        .await
+        .is_none());
+}
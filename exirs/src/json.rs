@@ -0,0 +1,444 @@
+//! EXI for JSON ([EXI4JSON](https://www.w3.org/TR/exi-for-json/)): JSON encoded with a fixed
+//! schema, in which objects are `map` elements holding an element per member, named after its
+//! key, and arrays are `array` elements holding `string`, `number`, `boolean`, `null`, `map` and
+//! `array` elements.
+//!
+//! Keys that aren't valid element names are escaped as the note describes: a key that is one of
+//! those element names, or empty, is prefixed with `_.`, and any character that can't appear in
+//! the name is written as `_`, its code point in decimal, and `.`.
+
+use std::{fmt::Write as _, sync::OnceLock};
+
+use bytes::Bytes;
+use serde_json::{Map, Number, Value as Json};
+
+use crate::{
+    config::{Header, Options, Schema},
+    data::{Event, Name, OwnedEvent, OwnedName, OwnedValue, Value},
+    error::SchemaError,
+    xml::{self, XmlError},
+    Error, Reader, Writer,
+};
+
+/// The namespace of every EXI4JSON element
+pub const NAMESPACE: &str = "http://www.w3.org/2015/EXI/json";
+
+/// The EXI4JSON schema, as XML, from Appendix A of the note
+pub const XSD: &str = include_str!("../schemas/exi4json.xsd");
+
+/// Element names keys are escaped from
+const NAMES: [&str; 7] = [
+    "map", "array", "string", "number", "boolean", "null", "other",
+];
+
+/// Integers with a larger magnitude can't all be represented as a double
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum JsonError {
+    #[error("only objects and arrays can be encoded as documents")]
+    NotContainer,
+    #[error("not EXI4JSON: {0}")]
+    Invalid(String),
+    /// EXIP's integers are 64-bit and signed, and the number can't be written as a double
+    /// without losing precision
+    #[error("{0} is too large to encode")]
+    OutOfRange(u64),
+    #[error("could not encode the EXI4JSON schema: {0}")]
+    Xsd(#[from] XmlError),
+    #[error(transparent)]
+    Schema(#[from] SchemaError<'static>),
+    #[error(transparent)]
+    Exi(#[from] Error),
+}
+
+/// Build the EXI4JSON grammars.
+///
+/// The XSD is encoded once, but the grammars are generated from it on each call, so reuse the
+/// schema, with `Writer::with_schema` and `Reader::with_schema`, for more than a few documents.
+pub fn schema() -> Result<Schema, JsonError> {
+    static ENCODED: OnceLock<Bytes> = OnceLock::new();
+    let xsd = match ENCODED.get() {
+        Some(xsd) => xsd,
+        None => {
+            // EXIP needs prefixes to resolve the type names in attribute values
+            let header = Header::with_options(Options::default().preserve_prefixes(true));
+            let xsd = xml::encode(XSD.as_bytes(), header, None)?;
+            ENCODED.get_or_init(|| xsd)
+        }
+    };
+    Ok(Schema::from_bytes(&[xsd], None)?)
+}
+
+/// Encode an object or array
+pub fn encode(json: &Json, header: Header) -> Result<Bytes, JsonError> {
+    let mut writer = Writer::new(header, Some(schema()?))?;
+    write(json, &mut writer)?;
+    Ok(Bytes::copy_from_slice(writer.get()))
+}
+
+/// Add an object or array to `writer`, from StartDocument to EndDocument.
+/// The writer must have the EXI4JSON schema.
+pub fn write(json: &Json, writer: &mut Writer) -> Result<(), JsonError> {
+    if !json.is_object() && !json.is_array() {
+        return Err(JsonError::NotContainer);
+    }
+    writer.add(Event::StartDocument)?;
+    write_value(json, writer)?;
+    writer.add(Event::EndDocument)?;
+    Ok(())
+}
+
+/// Decode an EXI4JSON document
+pub fn decode(bytes: impl Into<Bytes>) -> Result<Json, JsonError> {
+    read(&mut Reader::new(bytes, Some(schema()?), None)?)
+}
+
+/// Read the rest of an EXI4JSON document from `reader`, which must have the EXI4JSON schema
+pub fn read(reader: &mut Reader) -> Result<Json, JsonError> {
+    let mut event = next(reader)?;
+    if event == OwnedEvent::StartDocument {
+        event = next(reader)?;
+    }
+    let json = match event {
+        OwnedEvent::StartElement(name) => read_value(reader, &name)?,
+        event => return Err(unexpected(&event)),
+    };
+    match next(reader)? {
+        OwnedEvent::EndDocument => Ok(json),
+        event => Err(unexpected(&event)),
+    }
+}
+
+fn element(local_name: &str) -> Event<'_> {
+    Event::StartElement(Name {
+        local_name,
+        namespace: Some(NAMESPACE),
+        prefix: None,
+    })
+}
+
+fn write_value(json: &Json, writer: &mut Writer) -> Result<(), JsonError> {
+    match json {
+        Json::Object(map) => {
+            writer.add(element("map"))?;
+            for (key, value) in map {
+                writer.add(element(&escape(key)))?;
+                write_value(value, writer)?;
+                writer.add(Event::EndElement)?;
+            }
+        }
+        Json::Array(values) => {
+            writer.add(element("array"))?;
+            for value in values {
+                write_value(value, writer)?;
+            }
+        }
+        Json::String(string) => {
+            writer.add(element("string"))?;
+            writer.add(Event::Value(Value::String(string)))?;
+        }
+        Json::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(int), _) if int.unsigned_abs() > MAX_SAFE_INTEGER => {
+                writer.add(element("other"))?;
+                writer.add(element("integer"))?;
+                writer.add(Event::Value(Value::Integer(int)))?;
+                writer.add(Event::EndElement)?;
+            }
+            // Beyond `i64`, a double would round it
+            (None, Some(int)) => return Err(JsonError::OutOfRange(int)),
+            _ => {
+                writer.add(element("number"))?;
+                let double = number.as_f64().unwrap_or_default();
+                writer.add(Event::Value(Value::Float(double)))?;
+            }
+        },
+        Json::Bool(bool) => {
+            writer.add(element("boolean"))?;
+            writer.add(Event::Value(Value::Boolean(*bool)))?;
+        }
+        Json::Null => writer.add(element("null"))?,
+    }
+    writer.add(Event::EndElement)?;
+    Ok(())
+}
+
+/// The next event that matters to EXI4JSON
+fn next(reader: &mut Reader) -> Result<OwnedEvent, JsonError> {
+    loop {
+        let Some(event) = reader.next() else {
+            return Err(JsonError::Invalid("the stream ended early".into()));
+        };
+        match event?.into() {
+            OwnedEvent::NamespaceDeclaration(_)
+            | OwnedEvent::TypeAttribute(_)
+            | OwnedEvent::Attribute(_) => {}
+            event => return Ok(event),
+        }
+    }
+}
+
+fn unexpected(event: &OwnedEvent) -> JsonError {
+    JsonError::Invalid(format!("unexpected {:?}", event))
+}
+
+fn end(reader: &mut Reader) -> Result<(), JsonError> {
+    match next(reader)? {
+        OwnedEvent::EndElement => Ok(()),
+        event => Err(unexpected(&event)),
+    }
+}
+
+/// The values up to the end of the current element
+fn content(reader: &mut Reader) -> Result<Vec<OwnedValue>, JsonError> {
+    let mut values = vec![];
+    loop {
+        match next(reader)? {
+            OwnedEvent::Value(value) => values.push(value),
+            OwnedEvent::EndElement => return Ok(values),
+            event => return Err(unexpected(&event)),
+        }
+    }
+}
+
+fn text(reader: &mut Reader) -> Result<String, JsonError> {
+    let mut text = String::new();
+    for value in content(reader)? {
+        write!(text, "{}", value.as_value()).expect("writing to a string");
+    }
+    Ok(text)
+}
+
+fn number(reader: &mut Reader) -> Result<Number, JsonError> {
+    let values = content(reader)?;
+    let double = match values.as_slice() {
+        [OwnedValue::Integer(int)] => return Ok((*int).into()),
+        [OwnedValue::Float(double) | OwnedValue::Decimal(double)] => *double,
+        [OwnedValue::String(string)] => match string.trim().parse::<i64>() {
+            Ok(int) => return Ok(int.into()),
+            Err(_) => string
+                .trim()
+                .parse()
+                .map_err(|_| JsonError::Invalid(format!("`{}` is not a number", string)))?,
+        },
+        values => return Err(JsonError::Invalid(format!("{:?} is not a number", values))),
+    };
+    if double.fract() == 0.0 && double.abs() <= MAX_SAFE_INTEGER as f64 {
+        return Ok((double as i64).into());
+    }
+    Number::from_f64(double).ok_or_else(|| JsonError::Invalid(format!("JSON has no {}", double)))
+}
+
+/// Read the value an element started with `name` holds, up to and including its end
+fn read_value(reader: &mut Reader, name: &OwnedName) -> Result<Json, JsonError> {
+    if name.namespace.as_deref() != Some(NAMESPACE) {
+        return Err(JsonError::Invalid(format!(
+            "unexpected element `{}`",
+            name.local_name
+        )));
+    }
+    Ok(match name.local_name.as_str() {
+        "map" => {
+            let mut map = Map::new();
+            loop {
+                match next(reader)? {
+                    OwnedEvent::StartElement(key) => {
+                        let value = match next(reader)? {
+                            OwnedEvent::StartElement(name) => read_value(reader, &name)?,
+                            event => return Err(unexpected(&event)),
+                        };
+                        end(reader)?;
+                        map.insert(unescape(&key.local_name), value);
+                    }
+                    OwnedEvent::EndElement => break Json::Object(map),
+                    event => return Err(unexpected(&event)),
+                }
+            }
+        }
+        "array" => {
+            let mut values = vec![];
+            loop {
+                match next(reader)? {
+                    OwnedEvent::StartElement(name) => values.push(read_value(reader, &name)?),
+                    OwnedEvent::EndElement => break Json::Array(values),
+                    event => return Err(unexpected(&event)),
+                }
+            }
+        }
+        "string" => Json::String(text(reader)?),
+        "number" => Json::Number(number(reader)?),
+        "boolean" => match content(reader)?.as_slice() {
+            [OwnedValue::Boolean(bool)] => Json::Bool(*bool),
+            [OwnedValue::String(string)] if matches!(string.trim(), "true" | "1") => {
+                Json::Bool(true)
+            }
+            [OwnedValue::String(string)] if matches!(string.trim(), "false" | "0") => {
+                Json::Bool(false)
+            }
+            values => return Err(JsonError::Invalid(format!("{:?} is not a boolean", values))),
+        },
+        "null" => {
+            end(reader)?;
+            Json::Null
+        }
+        // A value typed more precisely than JSON can, such as `other/dateTime`
+        "other" => {
+            let json = match next(reader)? {
+                OwnedEvent::StartElement(name) => match name.local_name.as_str() {
+                    "integer" | "decimal" => Json::Number(number(reader)?),
+                    _ => Json::String(text(reader)?),
+                },
+                event => return Err(unexpected(&event)),
+            };
+            end(reader)?;
+            json
+        }
+        local_name => {
+            return Err(JsonError::Invalid(format!(
+                "unexpected element `{}`",
+                local_name
+            )))
+        }
+    })
+}
+
+/// The element name a member with this key is written as
+fn escape(key: &str) -> String {
+    let mut name = String::new();
+    if key.is_empty() || NAMES.contains(&key) {
+        name.push_str("_.");
+    }
+    for (i, c) in key.chars().enumerate() {
+        let valid = match i {
+            0 => name_start_char(c),
+            _ => name_char(c),
+        };
+        if valid {
+            name.push(c);
+        } else {
+            write!(name, "_{}.", c as u32).expect("writing to a string");
+        }
+    }
+    name
+}
+
+/// Whether `c` can start an XML NCName. `_` can, but is escaped so it can mark escapes.
+fn name_start_char(c: char) -> bool {
+    matches!(c,
+        'A'..='Z'
+        | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}'
+    )
+}
+
+/// Whether `c` can appear in an XML NCName after the first character
+fn name_char(c: char) -> bool {
+    name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}'
+        )
+}
+
+/// The key of a member written as an element with this name
+fn unescape(name: &str) -> String {
+    let mut rest = name.strip_prefix("_.").unwrap_or(name);
+    let mut key = String::new();
+    while let Some(i) = rest.find('_') {
+        key.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let escaped = rest.split_once('.').and_then(|(code, after)| {
+            let c = code.parse().ok().and_then(char::from_u32)?;
+            Some((c, after))
+        });
+        match escaped {
+            Some((c, after)) => {
+                key.push(c);
+                rest = after;
+            }
+            None => key.push('_'),
+        }
+    }
+    key.push_str(rest);
+    key
+}
+
+#[test]
+fn key_names() {
+    for (key, name) in [
+        ("name", "name"),
+        ("kebab-case.key2", "kebab-case.key2"),
+        ("map", "_.map"),
+        ("", "_."),
+        ("a b", "a_32.b"),
+        ("_id", "_95.id"),
+        ("1st", "_49.st"),
+        ("ключ", "ключ"),
+        ("a:b", "a_58.b"),
+        ("a²", "a_178."),
+        ("a·b", "a·b"),
+        ("·a", "_183.a"),
+    ] {
+        assert_eq!(escape(key), name);
+        assert_eq!(unescape(name), key);
+    }
+}
+
+#[test]
+fn round_trip() {
+    use serde_json::json;
+
+    let json = json!({
+        "name": "sensor-7",
+        "": "empty key",
+        "map": { "nested": true },
+        "readings": [21.5, -3, 0, "", null, [], {}],
+        "total": 9007199254740993i64,
+        "on": false,
+    });
+    let mut schema = schema().unwrap();
+    let mut writer = Writer::with_schema(Header::default(), &mut schema).unwrap();
+    write(&json, &mut writer).unwrap();
+    let bytes = writer.get().to_vec();
+    drop(writer);
+    assert_eq!(
+        read(&mut Reader::with_schema(bytes.clone(), &mut schema, None).unwrap()).unwrap(),
+        json
+    );
+    assert_eq!(decode(bytes.clone()).unwrap(), json);
+    assert!(bytes.len() < json.to_string().len());
+
+    assert!(matches!(
+        encode(&json!("scalar"), Header::default()),
+        Err(JsonError::NotContainer)
+    ));
+    assert!(matches!(
+        write(
+            &json!([u64::MAX]),
+            &mut Writer::with_schema(Header::default(), &mut schema).unwrap()
+        ),
+        Err(JsonError::OutOfRange(u64::MAX))
+    ));
+}
+
+#[test]
+fn interoperable() {
+    // `{"a": null}`, encoded by hand from the grammars the EXI spec derives from the note's
+    // schema, in strict mode with the options out of band. A lax wildcard holds the member `a`,
+    // and the local name `null` is found in a table of the schema's 15 names and `a`.
+    let bytes: &[u8] = &[0x80, 0x40, 0x26, 0x1B, 0x40, 0x29];
+    let options = Options::default().strict(true);
+    let mut reader = Reader::new(bytes, Some(schema().unwrap()), Some(options)).unwrap();
+    assert_eq!(read(&mut reader).unwrap(), serde_json::json!({ "a": null }));
+}
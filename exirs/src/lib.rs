@@ -1,8 +1,27 @@
+#[cfg(feature = "codec")]
+pub mod codec;
 pub mod config;
 pub mod data;
+pub mod detect;
+pub mod dom;
 pub mod error;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod reader;
+pub mod schema;
+pub mod select;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(test)]
+mod testing;
+pub mod trace;
 pub mod writer;
+#[cfg(feature = "xml")]
+pub mod xml;
 
+pub use detect::detect;
+pub use error::Error;
 pub use reader::Reader;
 pub use writer::Writer;
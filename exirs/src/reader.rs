@@ -1,19 +1,38 @@
 use std::{
-    mem::{self, MaybeUninit},
+    any::Any,
+    ffi::CStr,
+    mem,
     os::raw::{c_char, c_uint, c_void},
+    panic::{self, AssertUnwindSafe},
 };
 
 use bytes::Bytes;
 
 use crate::{
-    config::{Options, Schema},
+    config::{HeaderInfo, Options, Schema, SchemaRef, SchemaRegistry},
     data::{from_qname, from_stringtype, Attribute, Event, Name, NamespaceDeclaration, Value},
-    error::EXIPError,
+    dom::Document,
+    error::{check, Diagnostic, EXIPError, Error, Operation, Severity},
+    select::Select,
+    trace::{Rule, Trace, Undeclared},
 };
 
 #[derive(Debug, Default)]
 struct Handler<'a> {
     state: HandlerState<'a>,
+    diagnostics: Option<DiagnosticSink<'a>>,
+    /// A panic from the diagnostic sink, resumed once EXIP has returned
+    panic: Option<Box<dyn Any + Send>>,
+    /// Inside an element being skipped, so only element boundaries are kept
+    skipping: bool,
+}
+
+struct DiagnosticSink<'a>(Box<dyn FnMut(&Diagnostic) + 'a>);
+
+impl<'a> std::fmt::Debug for DiagnosticSink<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DiagnosticSink")
+    }
 }
 
 #[derive(Debug, Default)]
@@ -26,14 +45,6 @@ enum HandlerState<'a> {
 }
 
 impl<'a> HandlerState<'a> {
-    fn take_event(&mut self) -> Event<'a> {
-        let inner = mem::replace(self, HandlerState::Empty);
-        match inner {
-            HandlerState::Event(e) => e,
-            _ => unreachable!("checked prior"),
-        }
-    }
-
     fn replace(&mut self, value: Value<'a>) -> Self {
         if let HandlerState::PartialAttribute(key) = mem::replace(self, HandlerState::Empty) {
             HandlerState::Event(Event::Attribute(Attribute { key, value }))
@@ -68,55 +79,92 @@ impl<'a> Handler<'a> {
     }
 
     fn attribute(&mut self, name: crate::data::Name<'a>) -> Result<(), crate::error::EXIPError> {
-        self.state = HandlerState::PartialAttribute(name);
+        if !self.skipping {
+            self.state = HandlerState::PartialAttribute(name);
+        }
         Ok(())
     }
 
-    fn string(&mut self, value: &'a str) -> Result<(), crate::error::EXIPError> {
-        self.state = self.state.replace(Value::String(value));
+    /// Store a value, unless it's being skipped
+    fn value(&mut self, value: impl FnOnce() -> Value<'a>) -> Result<(), EXIPError> {
+        if !self.skipping {
+            self.state = self.state.replace(value());
+        }
         Ok(())
     }
 
+    fn string(&mut self, value: &'a str) -> Result<(), crate::error::EXIPError> {
+        self.value(|| Value::String(value))
+    }
+
     fn decimal(&mut self, value: ffi::EXIFloat) -> Result<(), crate::error::EXIPError> {
-        self.state = self.state.replace(Value::Float(value.into()));
-        Ok(())
+        self.value(|| Value::Decimal(value.into()))
     }
 
     fn boolean(&mut self, value: bool) -> Result<(), crate::error::EXIPError> {
-        self.state = self.state.replace(Value::Boolean(value));
-        Ok(())
+        self.value(|| Value::Boolean(value))
     }
 
     fn datetime(&mut self, dt: &'a chrono::NaiveDateTime) -> Result<(), crate::error::EXIPError> {
-        self.state = HandlerState::Event(Event::Value(Value::Timestamp(dt)));
+        if !self.skipping {
+            self.state = HandlerState::Event(Event::Value(Value::Timestamp(dt)));
+        }
         Ok(())
     }
 
     fn binary(&mut self, bytes: &'a [u8]) -> Result<(), crate::error::EXIPError> {
         // EXIP immediately frees read bytes, so we need to copy
-        self.state = self
-            .state
-            .replace(Value::Binary(Bytes::copy_from_slice(bytes)));
-        Ok(())
+        self.value(|| Value::Binary(Bytes::copy_from_slice(bytes)))
     }
 
     fn qname(&mut self, name: crate::data::Name<'a>) -> Result<(), crate::error::EXIPError> {
-        self.state = HandlerState::Event(Event::Value(Value::QName(name)));
+        if !self.skipping {
+            self.state = HandlerState::Event(Event::Value(Value::QName(name)));
+        }
         Ok(())
     }
 
     fn int(&mut self, int: i64) -> Result<(), EXIPError> {
-        self.state = self.state.replace(Value::Integer(int));
-        Ok(())
+        self.value(|| Value::Integer(int))
     }
 
     fn float(&mut self, value: ffi::EXIFloat) -> Result<(), EXIPError> {
-        self.state = self.state.replace(Value::Float(value.into()));
-        Ok(())
+        self.value(|| Value::Float(value.into()))
     }
 
     fn list(&mut self, len: u32) -> Result<(), EXIPError> {
-        self.state = HandlerState::PartialList(vec![], len);
+        if !self.skipping {
+            self.state = HandlerState::PartialList(vec![], len);
+        }
+        Ok(())
+    }
+
+    fn diagnostic(
+        &mut self,
+        severity: Severity,
+        code: u32,
+        msg: *const c_char,
+    ) -> Result<(), EXIPError> {
+        if self.panic.is_some() {
+            return Err(EXIPError::Unexpected);
+        }
+        if let Some(DiagnosticSink(sink)) = &mut self.diagnostics {
+            let message = if msg.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(msg) }.to_string_lossy().into_owned()
+            };
+            let diagnostic = Diagnostic {
+                severity,
+                code,
+                message,
+            };
+            // Unwinding into EXIP's C frames is undefined, so stop parsing and resume it after
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| sink(&diagnostic))) {
+                self.panic = Some(payload);
+                return Err(EXIPError::Unexpected);
+            }
+        }
         Ok(())
     }
 
@@ -126,18 +174,34 @@ impl<'a> Handler<'a> {
         prefix: &'a str,
         is_local_element: bool,
     ) -> Result<(), EXIPError> {
-        self.state = HandlerState::Event(Event::NamespaceDeclaration(NamespaceDeclaration {
-            namespace,
-            prefix,
-            is_local_element,
-        }));
+        if !self.skipping {
+            self.state = HandlerState::Event(Event::NamespaceDeclaration(NamespaceDeclaration {
+                namespace,
+                prefix,
+                is_local_element,
+            }));
+        }
         Ok(())
     }
 }
 pub struct Reader<'a> {
     parser: Box<ffi::Parser>,
-    _buf: Bytes,
+    buf: Bytes,
     handler: Box<Handler<'a>>,
+    header: HeaderInfo,
+    // Number of events returned so far, and the local names of the open elements, borrowed
+    // from the events that started them
+    events: usize,
+    path: Vec<&'a str>,
+    // Read ahead by `peek`, but not yet returned
+    peeked: Option<Option<Result<Event<'a>, Error>>>,
+    // `EndDocument` has been returned, so EXIP has nothing more to parse
+    finished: bool,
+    // Recording a `Trace` of each event, and that of the last one read
+    tracing: bool,
+    trace: Option<Trace>,
+    // Must outlive the parser, which holds a pointer to it
+    _schema: Option<SchemaRef<'a>>,
 }
 
 impl<'a> Reader<'a> {
@@ -147,10 +211,41 @@ impl<'a> Reader<'a> {
         bytes: impl Into<Bytes>,
         schema: Option<Schema>,
         options: Option<Options>,
-    ) -> Result<Self, EXIPError> {
+    ) -> Result<Self, Error> {
+        let mut reader = Self::parse_header(bytes.into(), options)?;
+        reader.set_schema(schema.map(SchemaRef::Owned))?;
+        Ok(reader)
+    }
+
+    /// Create a reader with a schema that outlives it, so it can be used for other streams
+    pub fn with_schema(
+        bytes: impl Into<Bytes>,
+        schema: &'a mut Schema,
+        options: Option<Options>,
+    ) -> Result<Self, Error> {
+        let mut reader = Self::parse_header(bytes.into(), options)?;
+        reader.set_schema(Some(SchemaRef::Borrowed(schema)))?;
+        Ok(reader)
+    }
+
+    /// Create a reader that picks its schema from `registry`,
+    /// according to the schema ID in the stream's header.
+    ///
+    /// The schema is locked until the reader is dropped, and creating another reader of a stream
+    /// with the same schema meanwhile fails with `ErrorKind::SchemaBusy`.
+    pub fn with_registry(
+        bytes: impl Into<Bytes>,
+        registry: &'a SchemaRegistry,
+        options: Option<Options>,
+    ) -> Result<Self, Error> {
+        let mut reader = Self::parse_header(bytes.into(), options)?;
+        let schema = registry.resolve(&reader.parser.strm.header)?;
+        reader.set_schema(schema.map(SchemaRef::Locked))?;
+        Ok(reader)
+    }
+
+    fn parse_header(heap_buf: Bytes, options: Option<Options>) -> Result<Self, Error> {
         let has_options = options.is_some() as u32;
-        let mut parser: MaybeUninit<ffi::Parser> = MaybeUninit::uninit();
-        let heap_buf: Bytes = bytes.into();
         let buf_rep = ffi::BinaryBuffer {
             buf: heap_buf.as_ptr() as *mut _,
             bufLen: heap_buf.len(),
@@ -161,79 +256,344 @@ impl<'a> Reader<'a> {
             },
         };
         let handler = Box::<Handler>::default();
+        // Initialised in place, as EXIP keeps pointers into the parser
+        let mut parser = unsafe { Box::<ffi::Parser>::new_zeroed().assume_init() };
         let ec = unsafe {
             (ffi::parse.initParser).unwrap()(
-                parser.as_mut_ptr(),
+                parser.as_mut(),
                 buf_rep,
                 &*handler as *const _ as *mut _,
             )
         };
-        assert_eq!(ec, 0);
-        let mut parser = unsafe { parser.assume_init() };
+        check(ec, Operation::InitParser)?;
         parser.handler = new_handler();
-        if let Some(options) = options {
+        // Keep the options borrowed until the header is parsed
+        if let Some(options) = &options {
             parser.strm.header.opts = options.ffi()
         }
-        let ec = unsafe { (ffi::parse.parseHeader).unwrap()(&mut parser as *mut _, has_options) };
-        assert_eq!(ec, 0);
-        let ec = unsafe {
-            (ffi::parse.setSchema).unwrap()(
-                &mut parser as *mut _,
-                schema.map_or(std::ptr::null_mut(), |mut s| s.inner.as_mut()),
-            )
-        };
-        if ec != 0 {
-            return Err(ec.into());
+        let ec = unsafe { (ffi::parse.parseHeader).unwrap()(parser.as_mut(), has_options) };
+        if let Err(e) = check(ec, Operation::ParseHeader) {
+            let e = e.at_bit(bit_position(&parser.strm));
+            unsafe { (ffi::parse.destroyParser).unwrap()(parser.as_mut()) };
+            return Err(e);
         }
+        let header = HeaderInfo::new(&parser.strm.header, options.is_some());
         Ok(Self {
-            parser: Box::new(parser),
-            _buf: heap_buf,
+            parser,
+            buf: heap_buf,
             handler,
+            header,
+            events: 0,
+            path: vec![],
+            peeked: None,
+            finished: false,
+            tracing: false,
+            trace: None,
+            _schema: None,
         })
     }
-}
 
-impl<'a> Drop for Reader<'a> {
-    fn drop(&mut self) {
-        unsafe { (ffi::parse.destroyParser).unwrap()(self.parser.as_mut() as *mut _) }
+    /// Receive the warnings and errors EXIP reports while parsing.
+    ///
+    /// Non-fatal errors are tolerated, and parsing continues after they're reported.
+    /// `sink` is called from within EXIP, so if it panics, parsing stops and the panic resumes
+    /// from the `next` call that was parsing.
+    pub fn on_diagnostic(&mut self, sink: impl FnMut(&Diagnostic) + 'a) {
+        self.handler.diagnostics = Some(DiagnosticSink(Box::new(sink)));
     }
-}
 
-impl<'a> Iterator for Reader<'a> {
-    type Item = Result<Event<'a>, EXIPError>;
+    /// Forward EXIP's diagnostics to the `log` crate
+    #[cfg(feature = "log")]
+    pub fn log_diagnostics(&mut self) {
+        self.on_diagnostic(|d| match d.severity {
+            Severity::Warning => log::warn!("{}", d),
+            Severity::Error | Severity::Fatal => log::error!("{}", d),
+        })
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// The stream's header, and the options in effect for it
+    pub fn header(&self) -> &HeaderInfo {
+        &self.header
+    }
+
+    /// Length of the document in bytes, including its header and padding, once `EndDocument`
+    /// has been read
+    pub fn consumed(&self) -> Option<usize> {
+        // The end of a stream is padded to a whole byte
+        self.finished
+            .then(|| bit_position(&self.parser.strm).div_ceil(8))
+    }
+
+    /// Any bytes after the document, once `EndDocument` has been read
+    pub fn remaining(&self) -> Option<Bytes> {
+        self.consumed().map(|len| self.buf.slice(len..))
+    }
+
+    /// Whether the stream is a fragment, which can have any number of root elements
+    pub fn is_fragment(&self) -> bool {
+        self.header.options().is_fragment()
+    }
+
+    /// The next event, without consuming it.
+    ///
+    /// Attributes and lists are returned whole, as from `next`. `depth` and `current_path`
+    /// don't change until the event is consumed.
+    pub fn peek(&mut self) -> Option<&Result<Event<'a>, Error>> {
+        let peeked = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.fetch(),
+        };
+        self.peeked.insert(peeked).as_ref()
+    }
+
+    /// Record where each event is in the stream, and the grammar production it was decoded
+    /// with. This copies the grammar rule in effect before each event, so slows reading.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+        self.trace = None;
+    }
+
+    /// The trace of the event last returned by `next` or `peek`, when tracing
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Number of open elements
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Local names of the open elements, e.g. `/MultipleXSDsTest/EXIPEncoder`
+    pub fn current_path(&self) -> String {
+        format!("/{}", self.path.join("/"))
+    }
+
+    /// Read past the rest of the innermost open element, up to and including its `EndElement`.
+    ///
+    /// Values inside it are parsed but not returned, nor copied. Does nothing outside of an element.
+    pub fn skip_element(&mut self) -> Result<(), Error> {
+        let depth = self.depth();
+        if depth == 0 {
+            return Ok(());
+        }
+        self.handler.skipping = true;
+        let result = loop {
+            match self.next() {
+                Some(Ok(Event::EndElement)) if self.depth() < depth => break Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break Err(e),
+                None => break Ok(()),
+            }
+        };
+        self.handler.skipping = false;
+        result
+    }
+
+    /// Select the values at `path` as they're read, see `crate::select` for the syntax
+    pub fn select(self, path: &str) -> Result<Select<'a>, Error> {
+        Ok(Select::new(self, path.parse()?))
+    }
+
+    fn set_schema(&mut self, mut schema: Option<SchemaRef<'a>>) -> Result<(), Error> {
+        let ec = unsafe {
+            (ffi::parse.setSchema).unwrap()(
+                self.parser.as_mut(),
+                schema
+                    .as_mut()
+                    .map_or(std::ptr::null_mut(), |s| s.as_ptr()),
+            )
+        };
+        self._schema = schema;
+        check(ec, Operation::SetSchema)
+    }
+
+    /// Attach the current position in the stream to `err`
+    fn error(&self, err: Error) -> Error {
+        let mut path = self.current_path();
+        if let HandlerState::PartialAttribute(name) = &self.handler.state {
+            if !self.path.is_empty() {
+                path.push('/');
+            }
+            path.push('@');
+            path.push_str(name.local_name);
+        }
+        let err = match err.operation() {
+            Some(_) => err,
+            None => err.during(Operation::ParseNext),
+        };
+        err.at_event(self.events)
+            .at_path(path)
+            .at_bit(bit_position(&self.parser.strm))
+    }
+
+    /// Read the next event, tracing it if enabled
+    fn fetch(&mut self) -> Option<Result<Event<'a>, Error>> {
+        if !self.tracing {
+            return self.read_event();
+        }
+        let bit_offset = bit_position(&self.parser.strm);
+        let undeclared = self._schema.is_none().then(|| {
+            let options = self.header.options();
+            Undeclared {
+                prefixes: options.preserves_prefixes(),
+                self_contained: options.is_self_contained(),
+            }
+        });
+        // SAFETY: the parser's stream is initialised in `new` and destroyed in `drop`, and EXIP
+        // only runs within `parseNext`
+        let rule = unsafe { Rule::current(&self.parser.strm, undeclared) };
+        let event = self.read_event();
+        self.trace = match &event {
+            Some(Ok(event)) => Some(Trace::new(bit_offset, &self.parser.strm, rule, event)),
+            _ => None,
+        };
+        event
+    }
+
+    fn read_event(&mut self) -> Option<Result<Event<'a>, Error>> {
+        if self.finished {
+            return None;
+        }
         match mem::replace(&mut self.handler.state, HandlerState::Empty) {
             HandlerState::Event(Event::StartDocument) => Some(Ok(Event::StartDocument)),
             HandlerState::Event(Event::EndDocument) => None,
-            HandlerState::Empty => {
+            HandlerState::Empty => loop {
                 let ec = unsafe { (ffi::parse.parseNext).unwrap()(self.parser.as_mut()) };
+                if let Some(payload) = self.handler.panic.take() {
+                    panic::resume_unwind(payload);
+                }
                 match ec {
-                    ffi::errorCode_EXIP_OK => match &self.handler.state {
-                        HandlerState::PartialAttribute(_) | HandlerState::PartialList(_, _) => {
-                            self.next()
+                    ffi::errorCode_EXIP_OK => {
+                        match mem::replace(&mut self.handler.state, HandlerState::Empty) {
+                            HandlerState::Event(e) => return Some(Ok(e)),
+                            // Nothing complete yet, e.g. an attribute awaiting its value,
+                            // or an event we don't surface or are skipping
+                            HandlerState::Empty => {}
+                            partial => {
+                                self.handler.state = partial;
+                                return self.read_event();
+                            }
                         }
-                        _ => Some(Ok(self.handler.state.take_event())),
-                    },
-                    ffi::errorCode_EXIP_PARSING_COMPLETE => Some(Ok(Event::EndDocument)),
-                    e => Some(Err(e.into())),
+                    }
+                    ffi::errorCode_EXIP_PARSING_COMPLETE => return Some(Ok(Event::EndDocument)),
+                    e => return Some(Err(self.error(Error::from_code(e)))),
                 }
-            }
-            HandlerState::PartialList(mut vec, length) => match self.next()? {
+            },
+            HandlerState::PartialList(mut vec, length) => match self.read_event()? {
                 Ok(Event::Value(value)) => {
                     vec.push(value);
                     if vec.len() == length as usize {
-                        return Some(Ok(Event::Value(Value::List(vec))));
+                        Some(Ok(Event::Value(Value::List(vec))))
                     } else {
                         self.handler.state = HandlerState::PartialList(vec, length);
-                        self.next()
+                        self.read_event()
                     }
                 }
-                Ok(_) => Some(Err(EXIPError::Unexpected)),
+                Ok(_) => Some(Err(self.error(EXIPError::Unexpected.into()))),
                 Err(e) => Some(Err(e)),
             },
-            _ => Some(Err(EXIPError::Unexpected)),
+            _ => Some(Err(self.error(EXIPError::Unexpected.into()))),
+        }
+    }
+}
+
+fn bit_position(strm: &ffi::EXIStream) -> usize {
+    strm.context.bufferIndx * 8 + strm.context.bitPointer as usize
+}
+
+impl<'a> Drop for Reader<'a> {
+    fn drop(&mut self) {
+        unsafe { (ffi::parse.destroyParser).unwrap()(self.parser.as_mut() as *mut _) }
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Event<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.fetch(),
+        }?;
+        if let Ok(e) = &event {
+            match e {
+                Event::StartElement(name) => self.path.push(name.local_name),
+                Event::EndElement => {
+                    self.path.pop();
+                }
+                // EXIP may report the end again, as `EXIP_PARSING_COMPLETE`
+                Event::EndDocument => self.finished = true,
+                _ => {}
+            }
+            self.events += 1;
+        }
+        Some(event)
+    }
+}
+
+/// Reads documents that follow one another in the same bytes, as on a persistent connection
+pub struct MultiReader<'o> {
+    bytes: Bytes,
+    schema: Option<Schema>,
+    options: Option<Options<'o>>,
+}
+
+impl<'o> MultiReader<'o> {
+    /// Each document is read with `schema` and `options`, as by `Reader::new`
+    pub fn new(
+        bytes: impl Into<Bytes>,
+        schema: Option<Schema>,
+        options: Option<Options<'o>>,
+    ) -> Self {
+        Self {
+            bytes: bytes.into(),
+            schema,
+            options,
+        }
+    }
+
+    /// Read the next document with `f`. Whatever `f` leaves unread is skipped.
+    ///
+    /// Returns `None` once there are no bytes left, and after an error.
+    pub fn next_with<T>(
+        &mut self,
+        f: impl FnOnce(&mut Reader) -> Result<T, Error>,
+    ) -> Option<Result<T, Error>> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let bytes = std::mem::take(&mut self.bytes);
+        let reader = match &mut self.schema {
+            Some(schema) => Reader::with_schema(bytes, schema, self.options.clone()),
+            None => Reader::new(bytes, None, self.options.clone()),
+        };
+        let mut reader = match reader {
+            Ok(reader) => reader,
+            Err(e) => return Some(Err(e)),
+        };
+        let result = f(&mut reader).and_then(|value| {
+            for event in reader.by_ref() {
+                event?;
+            }
+            Ok(value)
+        });
+        if result.is_ok() {
+            self.bytes = reader.remaining().unwrap_or_default();
         }
+        Some(result)
+    }
+
+    /// The bytes of the documents not yet read
+    pub fn remaining(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl<'o> Iterator for MultiReader<'o> {
+    type Item = Result<Document, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(Document::read)
     }
 }
 
@@ -318,7 +678,11 @@ unsafe extern "C" fn invoke_binary(
     handler: *mut c_void,
 ) -> ffi::errorCode {
     let handler = &mut *(handler as *mut Handler);
-    let slice = std::slice::from_raw_parts(binary as *const u8, nbytes);
+    let slice = if binary.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(binary as *const u8, nbytes)
+    };
     match handler.binary(slice) {
         Ok(_) => ffi::errorCode_EXIP_OK,
         Err(e) => e as u32,
@@ -385,8 +749,42 @@ unsafe extern "C" fn invoke_nsdec(
     }
 }
 
+unsafe extern "C" fn invoke_warning(
+    code: ffi::errorCode,
+    msg: *const c_char,
+    handler: *mut c_void,
+) -> ffi::errorCode {
+    let handler = &mut *(handler as *mut Handler);
+    match handler.diagnostic(Severity::Warning, code, msg) {
+        Ok(_) => ffi::errorCode_EXIP_OK,
+        Err(e) => e as u32,
+    }
+}
+
+unsafe extern "C" fn invoke_error(
+    code: ffi::errorCode,
+    msg: *const c_char,
+    handler: *mut c_void,
+) -> ffi::errorCode {
+    let handler = &mut *(handler as *mut Handler);
+    match handler.diagnostic(Severity::Error, code, msg) {
+        // Recoverable, so let EXIP carry on
+        Ok(_) => ffi::errorCode_EXIP_OK,
+        Err(e) => e as u32,
+    }
+}
+
+unsafe extern "C" fn invoke_fatal_error(
+    code: ffi::errorCode,
+    msg: *const c_char,
+    handler: *mut c_void,
+) -> ffi::errorCode {
+    let handler = &mut *(handler as *mut Handler);
+    let _ = handler.diagnostic(Severity::Fatal, code, msg);
+    code
+}
+
 fn new_handler() -> ffi::ContentHandler {
-    // EXIP never calls these functions
     ffi::ContentHandler {
         startDocument: Some(invoke_start_document),
         endDocument: Some(invoke_end_document),
@@ -403,9 +801,9 @@ fn new_handler() -> ffi::ContentHandler {
         listData: Some(invoke_list),
         qnameData: Some(invoke_qname),
         namespaceDeclaration: Some(invoke_nsdec),
-        warning: None,
-        error: None,
-        fatalError: None,
+        warning: Some(invoke_warning),
+        error: Some(invoke_error),
+        fatalError: Some(invoke_fatal_error),
         processingInstruction: None,
         selfContained: None,
     }
@@ -447,35 +845,10 @@ fn simple_read() {
 
 #[test]
 fn full_read() {
-    let input = &[
-        0x24, 0x45, 0x58, 0x49, 0xA0, 0x49, 0x6E, 0x05, 0x30, 0x2E, 0x32, 0x48, 0x54, 0x68, 0x69,
-        0x73, 0x20, 0x69, 0x73, 0x20, 0x61, 0x6E, 0x20, 0x65, 0x78, 0x61, 0x6D, 0x70, 0x6C, 0x65,
-        0x20, 0x6F, 0x66, 0x20, 0x73, 0x65, 0x72, 0x69, 0x61, 0x6C, 0x69, 0x7A, 0x69, 0x6E, 0x67,
-        0x20, 0x45, 0x58, 0x49, 0x20, 0x73, 0x74, 0x72, 0x65, 0x61, 0x6D, 0x73, 0x20, 0x75, 0x73,
-        0x69, 0x6E, 0x67, 0x20, 0x45, 0x58, 0x49, 0x50, 0x20, 0x6C, 0x6F, 0x77, 0x20, 0x6C, 0x65,
-        0x76, 0x65, 0x6C, 0x20, 0x41, 0x50, 0x49, 0x21, 0x2A, 0x34, 0x34, 0xB9, 0x90, 0x34, 0xB9,
-        0x90, 0x30, 0x90, 0x3A, 0x32, 0xB9, 0xBA, 0x10, 0x37, 0xB3, 0x10, 0x38, 0x39, 0x37, 0xB1,
-        0xB2, 0xB9, 0xB9, 0xB4, 0xB7, 0x33, 0x90, 0x2C, 0x26, 0xA6, 0x10, 0x39, 0xB1, 0xB4, 0x32,
-        0xB6, 0xB2, 0xB9, 0x90, 0x3B, 0xB4, 0xBA, 0x34, 0x10, 0x36, 0xBA, 0xB6, 0x3A, 0x34, 0xB8,
-        0x36, 0x32, 0x90, 0x2C, 0x29, 0xA2, 0x10, 0x33, 0x34, 0xB6, 0x32, 0xB9, 0x89, 0xD5, 0x99,
-        0x5C, 0x9A, 0x59, 0x9E, 0x48, 0x1D, 0x1A, 0x18, 0x5D, 0x08, 0x1D, 0x1A, 0x19, 0x48, 0x1A,
-        0x5B, 0x5C, 0x1B, 0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x18, 0x5D, 0x1A, 0x5B, 0xDB, 0x88, 0x1D,
-        0xDB, 0xDC, 0x9A, 0xDC, 0xC8, 0x4A, 0xD4, 0xDA, 0x5B, 0x5C, 0x1B, 0x19, 0x48, 0x1D, 0x19,
-        0x5C, 0xDD, 0x08, 0x19, 0x5B, 0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x08, 0x1D, 0xDA, 0x5D, 0x1A,
-        0x08, 0x1C, 0xDA, 0x5B, 0x99, 0xDB, 0x19, 0x48, 0x18, 0x5D, 0x1D, 0x1C, 0x9A, 0x58, 0x9D,
-        0x5D, 0x19, 0x47, 0x48, 0x3A, 0x8B, 0x06, 0x3F, 0xDB, 0x0E, 0xF9, 0x0A, 0x03, 0x90, 0x14,
-        0x04, 0xDA, 0x5F, 0x4A, 0x41, 0xE4, 0xC3, 0x39, 0xDC, 0x1E, 0xC0,
-    ];
-    let schema = Schema::new(
-        &[
-            "./examples/exipe-test-xsd.exi",
-            "./examples/exipe-test-types-xsd.exi",
-            "./examples/exipe-test-nested-xsd.exi",
-        ],
-        None,
-    )
-    .unwrap();
-    let mut reader = Reader::new(Bytes::from_static(input), Some(schema), None).unwrap();
+    use crate::testing::{example_schema, FULL_DOCUMENT};
+
+    let schema = example_schema(None);
+    let mut reader = Reader::new(Bytes::from_static(FULL_DOCUMENT), Some(schema), None).unwrap();
     assert_eq!(reader.next(), Some(Ok(Event::StartDocument)));
     assert_eq!(
         reader.next(),
@@ -658,3 +1031,521 @@ fn full_read() {
     assert_eq!(reader.next(), Some(Ok(Event::EndElement)));
     assert_eq!(reader.next(), Some(Ok(Event::EndDocument)));
 }
+
+#[test]
+fn registry_unknown_schema_id() {
+    use crate::{
+        config::{Header, SchemaIdMode},
+        error::ErrorKind,
+        writer::Writer,
+    };
+
+    let options = Options::default()
+        .schema_id_mode(SchemaIdMode::Set)
+        .schema_id("v2");
+    let mut builder = Writer::new(Header::with_options(options).has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    let registry = SchemaRegistry::new();
+    let err = Reader::with_registry(Bytes::copy_from_slice(builder.get()), &registry, None)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::UnknownSchemaId("v2".to_string())
+    );
+}
+
+#[test]
+fn registry_nil_schema_id() {
+    use crate::{
+        config::{Header, SchemaIdMode},
+        writer::Writer,
+    };
+
+    let options = Options::default().schema_id_mode(SchemaIdMode::Nil);
+    let mut builder = Writer::new(Header::with_options(options).has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::Value(Value::String("schemaless"))).unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    // The registry is shared by readers open at the same time
+    let registry = SchemaRegistry::new();
+    let bytes = Bytes::copy_from_slice(builder.get());
+    let first = Reader::with_registry(bytes.clone(), &registry, None).unwrap();
+    let second = Reader::with_registry(bytes, &registry, None).unwrap();
+    for reader in [first, second] {
+        let events = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(events[2], Event::Value(Value::String("schemaless")));
+    }
+}
+
+#[test]
+fn stream_header() {
+    use crate::{
+        config::{Header, OptionsSource},
+        writer::Writer,
+    };
+
+    let options = Options::default().strict(true);
+    let mut builder = Writer::new(Header::with_options(options).has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    let reader = Reader::new(Bytes::copy_from_slice(builder.get()), None, None).unwrap();
+    assert_eq!(reader.header().source(), OptionsSource::Stream);
+    assert!(reader.header().header().cookie_present());
+    assert!(reader.header().options().is_strict());
+}
+
+#[test]
+fn registry_busy_schema() {
+    use crate::testing::example_schema;
+    use crate::{
+        config::{Header, SchemaIdMode},
+        error::ErrorKind,
+        writer::Writer,
+    };
+
+    let options = Options::default()
+        .schema_id_mode(SchemaIdMode::Set)
+        .schema_id("v1");
+    let mut builder = Writer::new(Header::with_options(options).has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    let schema = example_schema(None);
+    let mut registry = SchemaRegistry::new();
+    registry.insert("v1", schema);
+    let bytes = Bytes::copy_from_slice(builder.get());
+    let first = Reader::with_registry(bytes.clone(), &registry, None).unwrap();
+    let err = Reader::with_registry(bytes.clone(), &registry, None)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), &ErrorKind::SchemaBusy);
+    drop(first);
+    assert!(Reader::with_registry(bytes, &registry, None).is_ok());
+}
+
+#[test]
+fn out_of_band_header() {
+    use crate::{
+        config::{Header, OptionsSource},
+        writer::Writer,
+    };
+
+    let mut builder = Writer::new(Header::new().has_cookie(true), None).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "root",
+            namespace: None,
+            prefix: None,
+        }))
+        .unwrap();
+    builder.add(Event::EndElement).unwrap();
+    builder.add(Event::EndDocument).unwrap();
+
+    let bytes = Bytes::copy_from_slice(builder.get());
+    let reader = Reader::new(bytes.clone(), None, None).unwrap();
+    assert_eq!(reader.header().source(), OptionsSource::Default);
+    assert!(reader.header().header().options().is_none());
+    let reader = Reader::new(bytes, None, Some(Options::default())).unwrap();
+    assert_eq!(reader.header().source(), OptionsSource::OutOfBand);
+    assert!(!reader.header().options().is_strict());
+}
+
+#[test]
+fn malformed_header() {
+    assert!(Reader::new(Bytes::new(), None, None).is_err());
+    // `$EXJ` instead of the `$EXI` cookie
+    assert!(Reader::new(Bytes::from_static(&[36, 69, 88, 74, 160]), None, None).is_err());
+    // Distinguishing bits `11` instead of `10`
+    assert!(Reader::new(Bytes::from_static(&[0xC0, 0x00]), None, None).is_err());
+}
+
+#[test]
+fn diagnostics() {
+    use std::{cell::RefCell, ffi::CString};
+
+    let seen = RefCell::new(vec![]);
+    let mut handler = Handler {
+        diagnostics: Some(DiagnosticSink(Box::new(|d: &Diagnostic| {
+            seen.borrow_mut().push(d.clone())
+        }))),
+        ..Default::default()
+    };
+    let msg = CString::new("unexpected value").unwrap();
+    let ec = unsafe {
+        invoke_warning(
+            ffi::errorCode_EXIP_INVALID_EXI_INPUT,
+            msg.as_ptr(),
+            &mut handler as *mut _ as *mut c_void,
+        )
+    };
+    assert_eq!(ec, ffi::errorCode_EXIP_OK);
+    let ec = unsafe {
+        invoke_fatal_error(
+            ffi::errorCode_EXIP_INVALID_EXI_INPUT,
+            std::ptr::null(),
+            &mut handler as *mut _ as *mut c_void,
+        )
+    };
+    assert_eq!(ec, ffi::errorCode_EXIP_INVALID_EXI_INPUT);
+    drop(handler);
+    let seen = seen.into_inner();
+    assert_eq!(seen.len(), 2);
+    assert_eq!(seen[0].severity, Severity::Warning);
+    assert_eq!(seen[0].message, "unexpected value");
+    assert_eq!(seen[0].error(), Some(EXIPError::InvalidEXIInput));
+    assert_eq!(seen[1].severity, Severity::Fatal);
+    assert_eq!(seen[1].message, "");
+}
+
+#[test]
+fn diagnostics_while_reading() {
+    use crate::error::ErrorKind;
+    use std::cell::RefCell;
+
+    // A schemaless header, then a local name whose length never ends
+    let input = &[0x80, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    let seen = RefCell::new(vec![]);
+    let mut reader = Reader::new(Bytes::from_static(input), None, None).unwrap();
+    reader.on_diagnostic(|d| seen.borrow_mut().push(d.clone()));
+    let err = loop {
+        match reader.next() {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => break e,
+            None => panic!("read a malformed stream"),
+        }
+    };
+    drop(reader);
+    let seen = seen.into_inner();
+    let fatal = seen.last().expect("no diagnostics");
+    assert_eq!(fatal.severity, Severity::Fatal);
+    assert_eq!(
+        Some(err.kind()),
+        fatal.error().map(ErrorKind::EXIP).as_ref()
+    );
+}
+
+#[test]
+fn diagnostic_panics() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Caught before it reaches EXIP, which stops parsing
+    let mut handler = Handler {
+        diagnostics: Some(DiagnosticSink(Box::new(|_: &Diagnostic| panic!("sink failed")))),
+        ..Default::default()
+    };
+    let ec = unsafe {
+        invoke_warning(
+            ffi::errorCode_EXIP_INVALID_EXI_INPUT,
+            std::ptr::null(),
+            &mut handler as *mut _ as *mut c_void,
+        )
+    };
+    assert_eq!(ec, EXIPError::Unexpected as u32);
+    assert!(handler.panic.is_some());
+
+    // Then resumed from `next`
+    let input = &[0x80, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    let mut reader = Reader::new(Bytes::from_static(input), None, None).unwrap();
+    reader.on_diagnostic(|_| panic!("sink failed"));
+    let payload = catch_unwind(AssertUnwindSafe(|| while let Some(Ok(_)) = reader.next() {}))
+        .expect_err("the sink's panic wasn't resumed");
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"sink failed"));
+}
+
+#[test]
+fn skip_element() {
+    use crate::{config::Header, Writer};
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("order", |e| {
+                e.element("customer", |e| e.text("Ada"))?;
+                e.element("item", |e| {
+                    e.attr("sku", Value::String("A1"))?;
+                    e.value(Value::Binary(Bytes::from_static(&[1, 2, 3])))
+                })?;
+                e.element("box", |e| e.element("item", |e| e.text("1")))?;
+                e.element("note", |e| e.text("fragile"))
+            })
+        })
+        .unwrap();
+    let mut reader = Reader::new(writer.get().to_vec(), None, None).unwrap();
+    let name = |local_name| Event::StartElement(Name::from(local_name));
+
+    assert_eq!(reader.next(), Some(Ok(Event::StartDocument)));
+    assert_eq!(reader.skip_element(), Ok(()));
+    assert_eq!((reader.depth(), reader.current_path().as_str()), (0, "/"));
+    assert_eq!(reader.next(), Some(Ok(name("order"))));
+    assert_eq!(reader.next(), Some(Ok(name("customer"))));
+    assert_eq!(reader.current_path(), "/order/customer");
+    reader.skip_element().unwrap();
+    assert_eq!(reader.depth(), 1);
+    assert_eq!(reader.next(), Some(Ok(name("item"))));
+    reader.skip_element().unwrap();
+    assert_eq!(reader.next(), Some(Ok(name("box"))));
+    assert_eq!(reader.next(), Some(Ok(name("item"))));
+    assert_eq!(
+        (reader.depth(), reader.current_path().as_str()),
+        (3, "/order/box/item")
+    );
+    reader.skip_element().unwrap();
+    assert_eq!(reader.next(), Some(Ok(Event::EndElement)));
+    assert_eq!(reader.current_path(), "/order");
+    assert_eq!(reader.next(), Some(Ok(name("note"))));
+    assert_eq!(
+        reader.next(),
+        Some(Ok(Event::Value(Value::String("fragile"))))
+    );
+    assert_eq!(reader.next(), Some(Ok(Event::EndElement)));
+    assert_eq!(reader.next(), Some(Ok(Event::EndElement)));
+    assert_eq!(reader.next(), Some(Ok(Event::EndDocument)));
+}
+
+#[test]
+fn peek() {
+    use crate::{config::Header, Writer};
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("order", |e| {
+                e.attr("id", Value::String("7"))?;
+                e.element("customer", |e| e.text("Ada"))
+            })
+        })
+        .unwrap();
+    let mut reader = Reader::new(writer.get().to_vec(), None, None).unwrap();
+    let name = |local_name| Event::StartElement(Name::from(local_name));
+    let id = Event::Attribute(Attribute {
+        key: Name::from("id"),
+        value: Value::String("7"),
+    });
+
+    assert_eq!(reader.peek(), Some(&Ok(Event::StartDocument)));
+    assert_eq!(reader.next(), Some(Ok(Event::StartDocument)));
+    assert_eq!(reader.peek(), Some(&Ok(name("order"))));
+    assert_eq!(reader.depth(), 0);
+    assert_eq!(reader.next(), Some(Ok(name("order"))));
+    assert_eq!(reader.depth(), 1);
+    assert_eq!(reader.peek(), Some(&Ok(id.clone())));
+    assert_eq!(reader.peek(), Some(&Ok(id.clone())));
+    assert_eq!(reader.next(), Some(Ok(id)));
+    assert_eq!(reader.peek(), Some(&Ok(name("customer"))));
+    // Skipping starts from the last consumed event, not the peeked one
+    reader.skip_element().unwrap();
+    assert_eq!(reader.depth(), 0);
+    assert_eq!(reader.peek(), Some(&Ok(Event::EndDocument)));
+    assert_eq!(reader.next(), Some(Ok(Event::EndDocument)));
+    assert_eq!(reader.peek(), None);
+    assert_eq!(reader.next(), None);
+}
+
+#[test]
+fn schemaless_fragment() {
+    use crate::{
+        config::{Header, Options},
+        Writer,
+    };
+
+    let header = Header::with_options(Options::default().fragment(true));
+    let mut writer = Writer::new(header, None).unwrap();
+    writer.add(Event::StartDocument).unwrap();
+    for id in ["1", "2", "3"] {
+        writer
+            .add(Event::StartElement(Name::from("record")))
+            .unwrap();
+        writer
+            .add(Event::Attribute(Attribute {
+                key: Name::from("id"),
+                value: Value::String(id),
+            }))
+            .unwrap();
+        writer.add(Event::EndElement).unwrap();
+    }
+    writer.add(Event::EndDocument).unwrap();
+
+    let mut reader = Reader::new(writer.get().to_vec(), None, None).unwrap();
+    assert!(reader.is_fragment());
+    assert_eq!(reader.next(), Some(Ok(Event::StartDocument)));
+    for id in ["1", "2", "3"] {
+        assert_eq!(
+            reader.next(),
+            Some(Ok(Event::StartElement(Name::from("record"))))
+        );
+        assert_eq!(
+            reader.next(),
+            Some(Ok(Event::Attribute(Attribute {
+                key: Name::from("id"),
+                value: Value::String(id),
+            })))
+        );
+        assert_eq!(reader.next(), Some(Ok(Event::EndElement)));
+    }
+    assert_eq!(reader.next(), Some(Ok(Event::EndDocument)));
+    assert_eq!(reader.next(), None);
+    assert_eq!(reader.next(), None);
+}
+
+#[test]
+fn schema_fragment() {
+    use crate::testing::example_schema;
+    use crate::{
+        config::{Header, Options},
+        data::OwnedValue,
+        dom::Document,
+        Writer,
+    };
+
+    // The schema's grammars must be built for fragments too
+    let options = || Options::default().fragment(true);
+    let schema = || example_schema(Some(options()));
+    let nested = |local_name| Name {
+        local_name,
+        namespace: Some("http://www.ltu.se/EISLAB/nested-xsd"),
+        prefix: None,
+    };
+    let mut writer = Writer::new(Header::with_options(options()), Some(schema())).unwrap();
+    writer
+        .document(|d| {
+            d.element(nested("bool"), |e| e.value(Value::Boolean(true)))?;
+            d.element(nested("testSetup"), |e| {
+                e.attr("goal", Value::String("goal"))?;
+                e.text("setup")
+            })?;
+            d.element(nested("bool"), |e| e.value(Value::Boolean(false)))
+        })
+        .unwrap();
+
+    let mut reader = Reader::new(writer.get().to_vec(), Some(schema()), None).unwrap();
+    assert!(reader.is_fragment());
+    let document = Document::read(&mut reader).unwrap();
+    let roots: Vec<_> = document
+        .elements
+        .iter()
+        .map(|e| (e.name.local_name.as_str(), e.value().cloned()))
+        .collect();
+    assert_eq!(
+        roots,
+        [
+            ("bool", Some(OwnedValue::Boolean(true))),
+            ("testSetup", Some(OwnedValue::String("setup".into()))),
+            ("bool", Some(OwnedValue::Boolean(false))),
+        ]
+    );
+}
+
+#[test]
+fn concatenated_documents() {
+    use crate::{config::Header, data::OwnedValue, Writer};
+
+    let message = |text: &str| {
+        let mut writer = Writer::new(Header::default(), None).unwrap();
+        writer
+            .document(|d| d.element("message", |e| e.text(text)))
+            .unwrap();
+        writer.get().to_vec()
+    };
+    let (first, second) = (message("one"), message("two"));
+    let bytes = [first.clone(), second.clone()].concat();
+
+    let mut reader = Reader::new(bytes.clone(), None, None).unwrap();
+    assert_eq!(reader.consumed(), None);
+    for event in reader.by_ref() {
+        event.unwrap();
+    }
+    assert_eq!(reader.consumed(), Some(first.len()));
+    assert_eq!(reader.remaining().unwrap(), second);
+
+    let values: Vec<_> = MultiReader::new(bytes, None, None)
+        .map(|document| document.unwrap().root().unwrap().value().cloned())
+        .collect();
+    assert_eq!(
+        values,
+        [
+            Some(OwnedValue::String("one".into())),
+            Some(OwnedValue::String("two".into()))
+        ]
+    );
+}
+
+#[test]
+fn concatenated_schema_documents() {
+    use crate::testing::example_schema;
+    use crate::{config::Header, data::OwnedValue, Writer};
+
+    let schema = || example_schema(None);
+    let flag = |value: bool| {
+        let mut writer = Writer::new(Header::default(), Some(schema())).unwrap();
+        let name = Name {
+            local_name: "bool",
+            namespace: Some("http://www.ltu.se/EISLAB/nested-xsd"),
+            prefix: None,
+        };
+        writer
+            .document(|d| d.element(name, |e| e.value(Value::Boolean(value))))
+            .unwrap();
+        writer.get().to_vec()
+    };
+    let bytes = [flag(true), flag(false), flag(true)].concat();
+
+    // Bit-packed, so each document is padded to a whole byte
+    let mut multi = MultiReader::new(bytes, Some(schema()), None);
+    let mut values = vec![];
+    while let Some(value) = multi.next_with(|reader| {
+        reader.nth(1).transpose()?;
+        Ok(match reader.next().transpose()? {
+            Some(Event::Value(value)) => Some(OwnedValue::from(value)),
+            _ => None,
+        })
+    }) {
+        values.push(value.unwrap());
+    }
+    assert_eq!(
+        values,
+        [
+            Some(OwnedValue::Boolean(true)),
+            Some(OwnedValue::Boolean(false)),
+            Some(OwnedValue::Boolean(true))
+        ]
+    );
+    assert!(multi.remaining().is_empty());
+}
@@ -0,0 +1,335 @@
+use crate::{
+    config::Schema,
+    data::{from_stringtype, Name, OwnedValue},
+};
+
+/// `INDEX_MAX` in EXIP, used to mark a missing grammar
+const INDEX_MAX: usize = usize::MAX;
+
+/// The EXI datatype representation used for a simple type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypeClass {
+    /// Element-only or empty content
+    None,
+    String,
+    Float,
+    Decimal,
+    DateTime,
+    Year,
+    Date,
+    Month,
+    Time,
+    Boolean,
+    Binary,
+    List,
+    QName,
+    Untyped,
+    Integer,
+    SmallInteger,
+    NonNegativeInteger,
+}
+
+impl From<u32> for TypeClass {
+    fn from(value: u32) -> Self {
+        match value {
+            ffi::VALUE_TYPE_STRING => TypeClass::String,
+            ffi::VALUE_TYPE_FLOAT => TypeClass::Float,
+            ffi::VALUE_TYPE_DECIMAL => TypeClass::Decimal,
+            ffi::VALUE_TYPE_DATE_TIME => TypeClass::DateTime,
+            ffi::VALUE_TYPE_YEAR => TypeClass::Year,
+            ffi::VALUE_TYPE_DATE => TypeClass::Date,
+            ffi::VALUE_TYPE_MONTH => TypeClass::Month,
+            ffi::VALUE_TYPE_TIME => TypeClass::Time,
+            ffi::VALUE_TYPE_BOOLEAN => TypeClass::Boolean,
+            ffi::VALUE_TYPE_BINARY => TypeClass::Binary,
+            ffi::VALUE_TYPE_LIST => TypeClass::List,
+            ffi::VALUE_TYPE_QNAME => TypeClass::QName,
+            ffi::VALUE_TYPE_UNTYPED => TypeClass::Untyped,
+            ffi::VALUE_TYPE_INTEGER => TypeClass::Integer,
+            ffi::VALUE_TYPE_SMALL_INTEGER => TypeClass::SmallInteger,
+            ffi::VALUE_TYPE_NON_NEGATIVE_INT => TypeClass::NonNegativeInteger,
+            _ => TypeClass::None,
+        }
+    }
+}
+
+/// A top-level element declaration in the schema
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobalElement<'s> {
+    pub name: Name<'s>,
+    pub definition: TypeDefinition<'s>,
+}
+
+/// A named or anonymous type, as compiled into an EXI grammar
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeDefinition<'s> {
+    /// `None` for anonymous types
+    pub name: Option<Name<'s>>,
+    /// Type class of the character content, `TypeClass::None` if there is none
+    pub class: TypeClass,
+    /// Enumerated values of the character content, if restricted, or `Err` with the type class
+    /// if they're of a class that isn't read, such as dates and times
+    pub enumeration: Result<Vec<OwnedValue>, TypeClass>,
+    /// Child elements that may appear, in grammar order
+    pub particles: Vec<Name<'s>>,
+    pub attributes: Vec<Name<'s>>,
+}
+
+impl Schema {
+    /// All global element declarations, including those of imported schemas
+    pub fn elements(&self) -> Vec<GlobalElement<'_>> {
+        self.qnames()
+            .filter(|(_, entry)| entry.elemGrammar != INDEX_MAX)
+            .map(|(name, entry)| GlobalElement {
+                name,
+                definition: self.definition(entry.elemGrammar, None),
+            })
+            .collect()
+    }
+
+    /// All type grammars known to the schema, named types first.
+    ///
+    /// Anonymous types are the grammars not referenced by any type name,
+    /// which includes the grammars of elements with inline type definitions.
+    pub fn types(&self) -> Vec<TypeDefinition<'_>> {
+        let mut names = vec![None; self.inner.grammarTable.count];
+        for (name, entry) in self.qnames() {
+            if let Some(slot) = names.get_mut(entry.typeGrammar) {
+                *slot = Some(name);
+            }
+        }
+        let (named, anonymous): (Vec<_>, Vec<_>) = names
+            .into_iter()
+            .enumerate()
+            .partition(|(_, name)| name.is_some());
+        named
+            .into_iter()
+            .chain(anonymous)
+            .map(|(index, name)| self.definition(index, name))
+            .collect()
+    }
+
+    fn qnames(&self) -> impl Iterator<Item = (Name<'_>, &ffi::LnEntry)> {
+        let uris = unsafe { table(self.inner.uriTable.uri, self.inner.uriTable.count) };
+        uris.iter().flat_map(|uri| {
+            let namespace = from_stringtype(&uri.uriStr).filter(|ns| !ns.is_empty());
+            unsafe { table(uri.lnTable.ln, uri.lnTable.count) }
+                .iter()
+                .map(move |ln| (qualified(namespace, &ln.lnStr), ln))
+        })
+    }
+
+    fn name_of(&self, id: ffi::QNameID) -> Option<Name<'_>> {
+        let uri = unsafe { table(self.inner.uriTable.uri, self.inner.uriTable.count) }
+            .get(id.uriId as usize)?;
+        let ln = unsafe { table(uri.lnTable.ln, uri.lnTable.count) }.get(id.lnId)?;
+        let namespace = from_stringtype(&uri.uriStr).filter(|ns| !ns.is_empty());
+        Some(qualified(namespace, &ln.lnStr))
+    }
+
+    fn definition<'s>(&'s self, index: usize, name: Option<Name<'s>>) -> TypeDefinition<'s> {
+        let mut def = TypeDefinition {
+            name,
+            class: TypeClass::None,
+            enumeration: Ok(vec![]),
+            particles: vec![],
+            attributes: vec![],
+        };
+        let grammars = unsafe {
+            table(
+                self.inner.grammarTable.grammar,
+                self.inner.grammarTable.count,
+            )
+        };
+        let Some(grammar) = grammars.get(index) else {
+            return def;
+        };
+        for rule in unsafe { table(grammar.rule, grammar.count as usize) } {
+            for prod in unsafe { table(rule.production, rule.pCount) } {
+                // GET_PROD_EXI_EVENT in EXIP
+                match prod.content >> 24 {
+                    ffi::EventType_EVENT_SE_QNAME => {
+                        if let Some(name) = self.name_of(prod.qnameId) {
+                            if !def.particles.contains(&name) {
+                                def.particles.push(name);
+                            }
+                        }
+                    }
+                    ffi::EventType_EVENT_AT_QNAME => {
+                        if let Some(name) = self.name_of(prod.qnameId) {
+                            if !def.attributes.contains(&name) {
+                                def.attributes.push(name);
+                            }
+                        }
+                    }
+                    ffi::EventType_EVENT_CH if def.class == TypeClass::None => {
+                        def.class = self.type_class(prod.typeId);
+                        def.enumeration = self.enumeration(prod.typeId, def.class);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        def
+    }
+
+    fn type_class(&self, type_id: usize) -> TypeClass {
+        simple_type(&self.inner, type_id).map_or(TypeClass::None, type_class)
+    }
+
+    fn enumeration(&self, type_id: usize, class: TypeClass) -> Result<Vec<OwnedValue>, TypeClass> {
+        let defs = unsafe { table(self.inner.enumTable.enumDef, self.inner.enumTable.count) };
+        let Some(def) = defs.iter().find(|def| def.typeId == type_id) else {
+            return Ok(vec![]);
+        };
+        // EXIP stores enumeration values as an array of the type's native representation
+        Ok(match class {
+            TypeClass::String => unsafe { table(def.values as *const ffi::String, def.count) }
+                .iter()
+                .map(|s| OwnedValue::String(from_stringtype(s).unwrap_or_default().into()))
+                .collect(),
+            TypeClass::Integer | TypeClass::SmallInteger | TypeClass::NonNegativeInteger => {
+                unsafe { table(def.values as *const ffi::Integer, def.count) }
+                    .iter()
+                    .map(|i| OwnedValue::Integer(*i))
+                    .collect()
+            }
+            TypeClass::Float => unsafe { table(def.values as *const ffi::EXIFloat, def.count) }
+                .iter()
+                .map(|f| OwnedValue::Float((*f).into()))
+                .collect(),
+            TypeClass::Decimal => unsafe { table(def.values as *const ffi::EXIFloat, def.count) }
+                .iter()
+                .map(|f| OwnedValue::Decimal((*f).into()))
+                .collect(),
+            TypeClass::Boolean => unsafe { table(def.values as *const ffi::boolean, def.count) }
+                .iter()
+                .map(|b| OwnedValue::Boolean(*b != 0))
+                .collect(),
+            // Such as dates and times, whose native representation isn't read
+            _ => return Err(class),
+        })
+    }
+}
+
+fn qualified<'s>(namespace: Option<&'s str>, local_name: &ffi::String) -> Name<'s> {
+    Name {
+        local_name: from_stringtype(local_name).unwrap_or_default(),
+        namespace,
+        prefix: None,
+    }
+}
+
+/// The simple type `type_id` in `schema`, if there is one
+pub(crate) fn simple_type(schema: &ffi::EXIPSchema, type_id: usize) -> Option<&ffi::SimpleType> {
+    unsafe { table(schema.simpleTypeTable.sType, schema.simpleTypeTable.count) }.get(type_id)
+}
+
+/// GET_EXI_TYPE in EXIP
+pub(crate) fn type_class(simple: &ffi::SimpleType) -> TypeClass {
+    TypeClass::from(simple.content >> 24)
+}
+
+/// View an EXIP dynamic array as a slice, tolerating empty tables
+///
+/// # Safety
+///
+/// Unless `ptr` is null or `count` is 0, `ptr` must point to `count` initialised `T`s that
+/// aren't changed or freed for as long as `'s`, which callers should tie to the schema or stream
+/// owning the array.
+pub(crate) unsafe fn table<'s, T>(ptr: *const T, count: usize) -> &'s [T] {
+    if ptr.is_null() || count == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, count)
+    }
+}
+
+#[test]
+fn global_elements() {
+    use crate::testing::example_schema;
+
+    let schema = example_schema(None);
+    let elements = schema.elements();
+    let root = elements
+        .iter()
+        .find(|e| e.name.local_name == "MultipleXSDsTest")
+        .unwrap();
+    assert_eq!(
+        root.name.namespace,
+        Some("http://www.ltu.se/EISLAB/schema-test")
+    );
+    assert!(root
+        .definition
+        .particles
+        .iter()
+        .any(|p| p.local_name == "EXIPEncoder"));
+    assert!(schema.types().iter().any(|t| t
+        .name
+        .as_ref()
+        .is_some_and(|n| n.local_name == "enumType")
+        && t.enumeration
+            .as_ref()
+            .is_ok_and(|e| e.contains(&OwnedValue::String("hej".into())))));
+}
+
+#[test]
+fn types() {
+    use crate::testing::example_schema;
+
+    assert_eq!(TypeClass::from(ffi::VALUE_TYPE_DATE), TypeClass::Date);
+    assert_eq!(TypeClass::from(u32::MAX), TypeClass::None);
+
+    let schema = example_schema(None);
+    let types = schema.types();
+    let anonymous = types.iter().position(|t| t.name.is_none()).unwrap();
+    assert!(types[..anonymous].iter().all(|t| t.name.is_some()));
+    assert!(types[anonymous..].iter().all(|t| t.name.is_none()));
+
+    // The built-in types
+    let class_of = |local_name: &str| {
+        let def = types
+            .iter()
+            .find(|t| {
+                t.name.as_ref().is_some_and(|n| {
+                    n.namespace == Some("http://www.w3.org/2001/XMLSchema")
+                        && n.local_name == local_name
+                })
+            })
+            .unwrap_or_else(|| panic!("no type {}", local_name));
+        assert_eq!(def.enumeration, Ok(vec![]), "{}", local_name);
+        def.class
+    };
+    assert_eq!(class_of("string"), TypeClass::String);
+    assert_eq!(class_of("boolean"), TypeClass::Boolean);
+    assert_eq!(class_of("decimal"), TypeClass::Decimal);
+    assert_eq!(class_of("double"), TypeClass::Float);
+    assert_eq!(class_of("integer"), TypeClass::Integer);
+    assert_eq!(class_of("nonNegativeInteger"), TypeClass::NonNegativeInteger);
+    assert_eq!(class_of("dateTime"), TypeClass::DateTime);
+    assert_eq!(class_of("date"), TypeClass::Date);
+    assert_eq!(class_of("base64Binary"), TypeClass::Binary);
+    assert_eq!(class_of("QName"), TypeClass::QName);
+}
+
+#[test]
+fn enumerations() {
+    use crate::testing::example_schema;
+
+    let schema = example_schema(None);
+    let enum_type = schema
+        .types()
+        .into_iter()
+        .find(|t| t.name.as_ref().is_some_and(|n| n.local_name == "enumType"))
+        .unwrap();
+    assert_eq!(enum_type.class, TypeClass::String);
+    let values = enum_type.enumeration.unwrap();
+    assert!(values.contains(&OwnedValue::String("hej".into())));
+    assert!(values.iter().all(|v| matches!(v, OwnedValue::String(_))));
+
+    // Values of classes that aren't read are reported, rather than left out
+    let defs = unsafe { table(schema.inner.enumTable.enumDef, schema.inner.enumTable.count) };
+    let type_id = defs.first().unwrap().typeId;
+    assert_eq!(schema.enumeration(type_id, TypeClass::Date), Err(TypeClass::Date));
+    assert_eq!(schema.enumeration(type_id, TypeClass::List), Err(TypeClass::List));
+}
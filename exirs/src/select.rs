@@ -0,0 +1,355 @@
+//! Selecting values from a stream by path, without building a tree.
+//!
+//! Paths are a small subset of XPath: absolute location paths of element names, `*` and `//`,
+//! optionally ending in an attribute, as in `/MultipleXSDsTest/type-test/@id` or `//item/@*`.
+//! Names match on the local name, or in Clark notation, `{namespace}local`, on the namespace too.
+//! A path to an element selects the values directly inside it.
+
+use std::str::FromStr;
+
+use crate::{
+    data::{Event, Name, OwnedValue},
+    error::ErrorKind,
+    Error, Reader,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum NameTest {
+    Any,
+    Name {
+        /// `None` matches any namespace, `Some("")` none
+        namespace: Option<String>,
+        local_name: String,
+    },
+}
+
+impl NameTest {
+    fn parse(test: &str) -> Result<Self, &'static str> {
+        if test == "*" {
+            return Ok(NameTest::Any);
+        }
+        let (namespace, local_name) = match test.strip_prefix('{') {
+            Some(clark) => match clark.split_once('}') {
+                Some((namespace, local_name)) => (Some(namespace.to_string()), local_name),
+                None => return Err("unclosed namespace"),
+            },
+            None => (None, test),
+        };
+        if local_name.is_empty() {
+            return Err("empty name");
+        }
+        if local_name.contains([':', '{', '}', '[', '(']) {
+            return Err("only names, `*` and `{namespace}local` are supported");
+        }
+        Ok(NameTest::Name {
+            namespace,
+            local_name: local_name.to_string(),
+        })
+    }
+
+    fn matches(&self, name: &Name) -> bool {
+        match self {
+            NameTest::Any => true,
+            NameTest::Name {
+                namespace,
+                local_name,
+            } => {
+                name.local_name == local_name
+                    && namespace
+                        .as_deref()
+                        .is_none_or(|ns| name.namespace.unwrap_or("") == ns)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Step {
+    /// Preceded by `//`, so any number of elements can come before it
+    descendant: bool,
+    name: NameTest,
+}
+
+/// A parsed path, see the module documentation for the syntax
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    steps: Vec<Step>,
+    attribute: Option<NameTest>,
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self, Error> {
+        Self::parse(path).map_err(|reason| ErrorKind::InvalidPath(path.to_string(), reason).into())
+    }
+}
+
+impl Path {
+    fn parse(mut rest: &str) -> Result<Self, &'static str> {
+        let mut path = Path {
+            steps: vec![],
+            attribute: None,
+        };
+        while !rest.is_empty() {
+            if path.attribute.is_some() {
+                return Err("attributes must be the last step");
+            }
+            let descendant = match rest.strip_prefix("//") {
+                Some(r) => {
+                    rest = r;
+                    true
+                }
+                None => {
+                    rest = rest.strip_prefix('/').ok_or("paths must be absolute")?;
+                    false
+                }
+            };
+            // Up to the next `/` outside of a namespace
+            let end = rest
+                .char_indices()
+                .scan(false, |in_namespace, (i, c)| {
+                    match c {
+                        '{' => *in_namespace = true,
+                        '}' => *in_namespace = false,
+                        _ => {}
+                    }
+                    Some((i, c, *in_namespace))
+                })
+                .find(|&(_, c, in_namespace)| c == '/' && !in_namespace)
+                .map_or(rest.len(), |(i, _, _)| i);
+            let (test, r) = rest.split_at(end);
+            rest = r;
+            match test.strip_prefix('@') {
+                Some(attribute) => {
+                    if descendant {
+                        path.steps.push(Step {
+                            descendant,
+                            name: NameTest::Any,
+                        });
+                    } else if path.steps.is_empty() {
+                        return Err("the document has no attributes");
+                    }
+                    path.attribute = Some(NameTest::parse(attribute)?);
+                }
+                None => path.steps.push(Step {
+                    descendant,
+                    name: NameTest::parse(test)?,
+                }),
+            }
+        }
+        if path.steps.is_empty() {
+            return Err("empty path");
+        }
+        Ok(path)
+    }
+
+    /// The steps matched by an element, given those matched by its parent
+    fn advance(&self, parent: &[usize], name: &Name) -> Vec<usize> {
+        let mut matched = vec![];
+        for &i in parent {
+            let Some(step) = self.steps.get(i) else {
+                continue;
+            };
+            if step.descendant {
+                matched.push(i);
+            }
+            if step.name.matches(name) {
+                matched.push(i + 1);
+            }
+        }
+        matched.dedup();
+        matched
+    }
+}
+
+/// Values matching a `Path`, as they're read
+pub struct Select<'a> {
+    reader: Reader<'a>,
+    path: Path,
+    /// Steps matched by each open element
+    open: Vec<Vec<usize>>,
+}
+
+impl<'a> Select<'a> {
+    pub fn new(reader: Reader<'a>, path: Path) -> Self {
+        Self {
+            reader,
+            path,
+            open: vec![],
+        }
+    }
+
+    /// Whether the innermost open element matches the whole path
+    fn matched(&self) -> bool {
+        self.open
+            .last()
+            .is_some_and(|steps| steps.contains(&self.path.steps.len()))
+    }
+}
+
+impl<'a> Iterator for Select<'a> {
+    type Item = Result<OwnedValue, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let event = match self.reader.next()? {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
+            };
+            match event {
+                Event::StartElement(name) => {
+                    let parent = self.open.last().map_or(&[0][..], Vec::as_slice);
+                    let steps = self.path.advance(parent, &name);
+                    if steps.is_empty() {
+                        // Nothing inside can match
+                        if let Err(e) = self.reader.skip_element() {
+                            return Some(Err(e));
+                        }
+                    } else {
+                        self.open.push(steps);
+                    }
+                }
+                Event::EndElement => {
+                    self.open.pop();
+                }
+                Event::Attribute(attr) if self.matched() => {
+                    if let Some(test) = &self.path.attribute {
+                        if test.matches(&attr.key) {
+                            return Some(Ok(attr.value.into()));
+                        }
+                    }
+                }
+                Event::Value(value) if self.matched() && self.path.attribute.is_none() => {
+                    return Some(Ok(value.into()))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_paths() {
+    let name = |local_name: &str| NameTest::Name {
+        namespace: None,
+        local_name: local_name.to_string(),
+    };
+    assert_eq!(
+        "/a//{http://example.com/ns}b/*/@c".parse::<Path>().unwrap(),
+        Path {
+            steps: vec![
+                Step {
+                    descendant: false,
+                    name: name("a"),
+                },
+                Step {
+                    descendant: true,
+                    name: NameTest::Name {
+                        namespace: Some("http://example.com/ns".into()),
+                        local_name: "b".into(),
+                    },
+                },
+                Step {
+                    descendant: false,
+                    name: NameTest::Any,
+                },
+            ],
+            attribute: Some(name("c")),
+        }
+    );
+    assert_eq!(
+        "//@id".parse::<Path>().unwrap(),
+        Path {
+            steps: vec![Step {
+                descendant: true,
+                name: NameTest::Any,
+            }],
+            attribute: Some(name("id")),
+        }
+    );
+    for invalid in ["", "a/b", "/a/@b/c", "/@a", "/a//", "/a[1]", "/{ns/a"] {
+        let err = invalid.parse::<Path>().unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::InvalidPath(..)),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn schemaless_select() {
+    use crate::config::Header;
+    use crate::data::Value;
+    use crate::Writer;
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("order", |e| {
+                e.attr("id", Value::String("7"))?;
+                e.element("customer", |e| e.text("Ada"))?;
+                e.element("item", |e| {
+                    e.attr("sku", Value::String("A1"))?;
+                    e.text("2")
+                })?;
+                e.element("box", |e| {
+                    e.element("item", |e| {
+                        e.attr("sku", Value::String("B2"))?;
+                        e.text("1")
+                    })
+                })
+            })
+        })
+        .unwrap();
+    let bytes = writer.get().to_vec();
+    let select = |path: &str| -> Vec<OwnedValue> {
+        Reader::new(bytes.clone(), None, None)
+            .unwrap()
+            .select(path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+    let s = |s: &str| OwnedValue::String(s.into());
+
+    assert_eq!(select("/order/@id"), [s("7")]);
+    assert_eq!(select("/order/customer"), [s("Ada")]);
+    assert_eq!(select("/order/item/@sku"), [s("A1")]);
+    assert_eq!(select("//item/@sku"), [s("A1"), s("B2")]);
+    assert_eq!(select("/order/*/item"), [s("1")]);
+    assert_eq!(select("//@*"), [s("7"), s("A1"), s("B2")]);
+    assert!(select("/order").is_empty());
+    assert!(select("/invoice//item").is_empty());
+}
+
+#[test]
+fn schema_select() {
+    use crate::testing::{example_document, example_schema};
+
+    let schema = || example_schema(None);
+    let bytes = example_document();
+    let select = |path: &str| -> Vec<OwnedValue> {
+        Reader::new(bytes.clone(), Some(schema()), None)
+            .unwrap()
+            .select(path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    };
+
+    assert_eq!(
+        select("/MultipleXSDsTest/type-test/@id"),
+        [OwnedValue::Integer(1001)]
+    );
+    assert_eq!(
+        select("//{http://www.ltu.se/EISLAB/nested-xsd}bool"),
+        [OwnedValue::Boolean(true)]
+    );
+    assert!(select("//{}bool").is_empty());
+    assert_eq!(
+        select("/*/extendedTypeTest/{}byteTest"),
+        [OwnedValue::Integer(11)]
+    );
+}
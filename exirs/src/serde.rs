@@ -0,0 +1,1887 @@
+//! Serialization and deserialization of Rust values with [serde](https://serde.rs).
+//!
+//! A struct is written as an element named after the type at the root, and after the field
+//! elsewhere. Fields are child elements, except those renamed to `@name`, which are attributes,
+//! and one renamed to `$value`, which is the element's character content. Attribute fields must
+//! come before element fields. Sequences are repeated elements, unit variants are text and other
+//! enum variants are elements named after the variant.
+//!
+//! Element and attribute names can carry a namespace in Clark notation, `{namespace}local`, or
+//! `@{namespace}local`. Other elements are in the serializer's namespace, if it has one, and
+//! attributes in none.
+//!
+//! Strings are taken as lexical values, so they're converted to the type the schema expects.
+//!
+//! Deserialization follows the same conventions. Typed values go straight to the visitor, and
+//! strings, as in schemaless streams, are only parsed when a number or bool is asked for.
+//! Enum variants with content can't be read back.
+
+use std::fmt::Display;
+
+use ::serde::{
+    de::{self, value::SeqDeserializer, DeserializeOwned, IntoDeserializer},
+    forward_to_deserialize_any,
+    ser::{self, Impossible, Serialize},
+};
+use bytes::Bytes;
+
+use crate::{
+    config::{Header, Schema},
+    data::{Attribute, Event, Name, OwnedEvent, OwnedName, OwnedValue, Value},
+    Error, Reader, Writer,
+};
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum SerdeError {
+    #[error("{0}")]
+    Custom(String),
+    /// The root value, or an attribute or map key, has an unsupported shape
+    #[error("{0} isn't supported here")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Exi(#[from] Error),
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError::Custom(msg.to_string())
+    }
+}
+
+/// Encode `value` as a document
+pub fn to_bytes<T: Serialize + ?Sized>(
+    value: &T,
+    header: Header,
+    schema: Option<Schema>,
+) -> Result<Bytes, SerdeError> {
+    let mut writer = Writer::new(header, schema)?;
+    writer.add(Event::StartDocument)?;
+    value.serialize(&mut Serializer::new(&mut writer))?;
+    writer.add(Event::EndDocument)?;
+    Ok(Bytes::copy_from_slice(writer.get()))
+}
+
+/// Writes a value as an element, without starting or ending the document
+pub struct Serializer<'w, 'sch> {
+    writer: &'w mut Writer<'sch>,
+    namespace: Option<&'w str>,
+}
+
+impl<'w, 'sch> Serializer<'w, 'sch> {
+    pub fn new(writer: &'w mut Writer<'sch>) -> Self {
+        Self {
+            writer,
+            namespace: None,
+        }
+    }
+
+    /// Namespace of elements without one in their name
+    pub fn with_namespace(mut self, namespace: &'w str) -> Self {
+        self.namespace = Some(namespace);
+        self
+    }
+
+    fn element<'s>(&'s mut self, name: &'s str) -> ElementSerializer<'s, 'sch> {
+        ElementSerializer {
+            writer: self.writer,
+            namespace: self.namespace,
+            name: Some(name),
+        }
+    }
+}
+
+/// Only named types can be the root element
+impl<'a, 'w, 'sch> ser::Serializer for &'a mut Serializer<'w, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+    type SerializeSeq = Impossible<(), SerdeError>;
+    type SerializeTuple = Impossible<(), SerdeError>;
+    type SerializeTupleStruct = SeqSerializer<'a, 'sch>;
+    type SerializeTupleVariant = SeqSerializer<'a, 'sch>;
+    type SerializeMap = Impossible<(), SerdeError>;
+    type SerializeStruct = FieldSerializer<'a, 'sch>;
+    type SerializeStructVariant = FieldSerializer<'a, 'sch>;
+
+    fn serialize_bool(self, _: bool) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SerdeError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_str(self, _: &str) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_none(self) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), SerdeError> {
+        ser::Serializer::serialize_unit(self.element(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), SerdeError> {
+        ser::Serializer::serialize_unit(self.element(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        value.serialize(self.element(name))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        value.serialize(self.element(variant))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        self.element(name).serialize_tuple_struct(name, len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        self.element(variant)
+            .serialize_tuple_variant(name, variant_index, variant, len)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Err(SerdeError::Unsupported("an unnamed root"))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        self.element(name).serialize_struct(name, len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        self.element(variant)
+            .serialize_struct_variant(name, variant_index, variant, len)
+    }
+}
+
+/// A value with no child elements
+#[derive(Clone, Debug)]
+enum Scalar {
+    Integer(i64),
+    Boolean(bool),
+    Float(f64),
+    Binary(Bytes),
+    /// A lexical value, converted to the expected type by the writer
+    Text(String),
+    List(Vec<Scalar>),
+}
+
+impl Scalar {
+    fn lexical(&self) -> String {
+        match self {
+            Scalar::Text(text) => text.clone(),
+            Scalar::List(items) => items
+                .iter()
+                .map(Scalar::lexical)
+                .collect::<Vec<_>>()
+                .join(" "),
+            typed => typed.with_value(|v| v.to_string()),
+        }
+    }
+
+    /// Call `f` with the typed `Value` of a scalar that isn't lexical
+    fn with_value<R>(&self, f: impl FnOnce(Value) -> R) -> R {
+        match self {
+            Scalar::Integer(i) => f(Value::Integer(*i)),
+            Scalar::Boolean(b) => f(Value::Boolean(*b)),
+            Scalar::Float(fl) => f(Value::Float(*fl)),
+            Scalar::Binary(bin) => f(Value::Binary(bin.clone())),
+            Scalar::Text(text) => f(Value::String(text)),
+            Scalar::List(_) => f(Value::String(&self.lexical())),
+        }
+    }
+
+    fn write_value(&self, writer: &mut Writer) -> Result<(), SerdeError> {
+        match self {
+            Scalar::Text(_) | Scalar::List(_) => {
+                writer.add_lexical(Event::Value(Value::String(&self.lexical())), &[])?
+            }
+            typed => typed.with_value(|value| writer.add(Event::Value(value)))?,
+        }
+        Ok(())
+    }
+
+    fn write_attribute(&self, writer: &mut Writer, key: Name) -> Result<(), SerdeError> {
+        match self {
+            Scalar::Text(_) | Scalar::List(_) => writer.add_lexical(
+                Event::Attribute(Attribute {
+                    key,
+                    value: Value::String(&self.lexical()),
+                }),
+                &[],
+            )?,
+            typed => {
+                typed.with_value(|value| writer.add(Event::Attribute(Attribute { key, value })))?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a name in Clark notation
+fn qualified<'n>(name: &'n str, namespace: Option<&'n str>) -> Name<'n> {
+    match name.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+        Some((ns, local_name)) => Name {
+            local_name,
+            namespace: Some(ns).filter(|ns| !ns.is_empty()),
+            prefix: None,
+        },
+        None => Name {
+            local_name: name,
+            namespace,
+            prefix: None,
+        },
+    }
+}
+
+/// Writes a value as an element named `name`, or as content if there's no name
+struct ElementSerializer<'s, 'sch> {
+    writer: &'s mut Writer<'sch>,
+    namespace: Option<&'s str>,
+    name: Option<&'s str>,
+}
+
+impl<'s, 'sch> ElementSerializer<'s, 'sch> {
+    fn start(&mut self, name: &str) -> Result<(), SerdeError> {
+        let name = qualified(name, self.namespace);
+        self.writer.add(Event::StartElement(name))?;
+        Ok(())
+    }
+
+    fn leaf(mut self, scalar: Scalar) -> Result<(), SerdeError> {
+        match self.name {
+            Some(name) => {
+                self.start(name)?;
+                scalar.write_value(self.writer)?;
+                self.writer.add(Event::EndElement)?;
+            }
+            None => scalar.write_value(self.writer)?,
+        }
+        Ok(())
+    }
+
+    fn maybe_leaf(self, scalar: Option<Scalar>) -> Result<(), SerdeError> {
+        match scalar {
+            Some(scalar) => self.leaf(scalar),
+            None => Ok(()),
+        }
+    }
+
+    fn fields(mut self, name: Option<&str>) -> Result<FieldSerializer<'s, 'sch>, SerdeError> {
+        let name = name.or(self.name).ok_or(SerdeError::Unsupported(
+            "a struct or map as character content",
+        ))?;
+        self.start(name)?;
+        Ok(FieldSerializer {
+            writer: self.writer,
+            namespace: self.namespace,
+            key: None,
+        })
+    }
+
+    fn seq(self, name: Option<&'s str>) -> SeqSerializer<'s, 'sch> {
+        SeqSerializer {
+            writer: self.writer,
+            namespace: self.namespace,
+            name: name.or(self.name),
+        }
+    }
+}
+
+impl<'s, 'sch> ser::Serializer for ElementSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+    type SerializeSeq = SeqSerializer<'s, 'sch>;
+    type SerializeTuple = SeqSerializer<'s, 'sch>;
+    type SerializeTupleStruct = SeqSerializer<'s, 'sch>;
+    type SerializeTupleVariant = SeqSerializer<'s, 'sch>;
+    type SerializeMap = FieldSerializer<'s, 'sch>;
+    type SerializeStruct = FieldSerializer<'s, 'sch>;
+    type SerializeStructVariant = FieldSerializer<'s, 'sch>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Boolean(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Integer(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), SerdeError> {
+        self.maybe_leaf(ScalarSerializer.serialize_i128(v)?)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), SerdeError> {
+        self.maybe_leaf(ScalarSerializer.serialize_u64(v)?)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), SerdeError> {
+        self.maybe_leaf(ScalarSerializer.serialize_u128(v)?)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Float(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SerdeError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Text(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Binary(Bytes::copy_from_slice(v)))
+    }
+
+    /// Absent values have no element
+    fn serialize_none(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    /// An empty element
+    fn serialize_unit(mut self) -> Result<(), SerdeError> {
+        if let Some(name) = self.name {
+            self.start(name)?;
+            self.writer.add(Event::EndElement)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), SerdeError> {
+        self.leaf(Scalar::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        value.serialize(ElementSerializer {
+            name: Some(variant),
+            ..self
+        })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        Ok(self.seq(None))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        Ok(self.seq(None))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        Ok(self.seq(None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Ok(self.seq(Some(variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        self.fields(None)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        self.fields(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        self.fields(Some(variant))
+    }
+}
+
+/// Writes each item as an element with the same name
+pub struct SeqSerializer<'s, 'sch> {
+    writer: &'s mut Writer<'sch>,
+    namespace: Option<&'s str>,
+    name: Option<&'s str>,
+}
+
+impl<'s, 'sch> SeqSerializer<'s, 'sch> {
+    fn item<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        value.serialize(ElementSerializer {
+            writer: self.writer,
+            namespace: self.namespace,
+            name: self.name,
+        })
+    }
+}
+
+impl<'s, 'sch> ser::SerializeSeq for SeqSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'s, 'sch> ser::SerializeTuple for SeqSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'s, 'sch> ser::SerializeTupleStruct for SeqSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'s, 'sch> ser::SerializeTupleVariant for SeqSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+/// Writes the fields of an open element, then ends it
+pub struct FieldSerializer<'s, 'sch> {
+    writer: &'s mut Writer<'sch>,
+    namespace: Option<&'s str>,
+    /// Map key awaiting its value
+    key: Option<String>,
+}
+
+impl<'s, 'sch> FieldSerializer<'s, 'sch> {
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), SerdeError> {
+        if let Some(attr) = key.strip_prefix('@') {
+            if let Some(scalar) = value.serialize(ScalarSerializer)? {
+                scalar.write_attribute(self.writer, qualified(attr, None))?;
+            }
+            return Ok(());
+        }
+        let name = if key == "$value" {
+            // Text, unless the value is a choice of elements
+            match value.serialize(ScalarSerializer) {
+                Ok(Some(scalar)) => return scalar.write_value(self.writer),
+                Ok(None) => return Ok(()),
+                Err(SerdeError::Unsupported(_)) => None,
+                Err(e) => return Err(e),
+            }
+        } else {
+            Some(key)
+        };
+        value.serialize(ElementSerializer {
+            writer: self.writer,
+            namespace: self.namespace,
+            name,
+        })
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        self.writer.add(Event::EndElement)?;
+        Ok(())
+    }
+}
+
+impl<'s, 'sch> ser::SerializeStruct for FieldSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        FieldSerializer::end(self)
+    }
+}
+
+impl<'s, 'sch> ser::SerializeStructVariant for FieldSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        FieldSerializer::end(self)
+    }
+}
+
+impl<'s, 'sch> ser::SerializeMap for FieldSerializer<'s, 'sch> {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        match key.serialize(ScalarSerializer)? {
+            Some(Scalar::List(_)) | None => Err(SerdeError::Unsupported("a compound map key")),
+            Some(scalar) => {
+                self.key = Some(scalar.lexical());
+                Ok(())
+            }
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or(SerdeError::Unsupported("a map value without a key"))?;
+        self.field(&key, value)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        FieldSerializer::end(self)
+    }
+}
+
+/// Captures a value with no child elements, `None` if it's absent
+struct ScalarSerializer;
+
+impl ser::Serializer for ScalarSerializer {
+    type Ok = Option<Scalar>;
+    type Error = SerdeError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = Impossible<Option<Scalar>, SerdeError>;
+    type SerializeMap = Impossible<Option<Scalar>, SerdeError>;
+    type SerializeStruct = Impossible<Option<Scalar>, SerdeError>;
+    type SerializeStructVariant = Impossible<Option<Scalar>, SerdeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Boolean(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Integer(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    /// Integers beyond `i64` are written lexically
+    fn serialize_i128(self, v: i128) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(match i64::try_from(v) {
+            Ok(v) => Scalar::Integer(v),
+            Err(_) => Scalar::Text(v.to_string()),
+        }))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i128(v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(match i64::try_from(v) {
+            Ok(v) => Scalar::Integer(v),
+            Err(_) => Scalar::Text(v.to_string()),
+        }))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Float(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<Scalar>, SerdeError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Text(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Text(v.to_string())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Binary(Bytes::copy_from_slice(v))))
+    }
+
+    fn serialize_none(self) -> Result<Option<Scalar>, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Option<Scalar>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Scalar>, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Scalar>, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::Text(variant.to_string())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<Scalar>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Option<Scalar>, SerdeError> {
+        Err(SerdeError::Unsupported("an enum variant with content"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ListSerializer, SerdeError> {
+        Ok(ListSerializer(Vec::with_capacity(len.unwrap_or_default())))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ListSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ListSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Err(SerdeError::Unsupported("an enum variant with content"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Err(SerdeError::Unsupported("a map"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        Err(SerdeError::Unsupported("a struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        Err(SerdeError::Unsupported("an enum variant with content"))
+    }
+}
+
+/// Collects a sequence of scalars into an `xs:list`
+struct ListSerializer(Vec<Scalar>);
+
+impl ListSerializer {
+    fn item<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        match value.serialize(ScalarSerializer)? {
+            Some(Scalar::List(_)) => Err(SerdeError::Unsupported("a nested list")),
+            Some(scalar) => {
+                self.0.push(scalar);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Scalar>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::List(self.0)))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Scalar>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::List(self.0)))
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Scalar>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.item(value)
+    }
+
+    fn end(self) -> Result<Option<Scalar>, SerdeError> {
+        Ok(Some(Scalar::List(self.0)))
+    }
+}
+
+/// Decode a document into a `T`
+pub fn from_bytes<T: DeserializeOwned>(
+    bytes: impl Into<Bytes>,
+    schema: Option<Schema>,
+) -> Result<T, SerdeError> {
+    from_reader(Reader::new(bytes, schema, None)?)
+}
+
+/// Read a document from `reader` into a `T`
+pub fn from_reader<T: DeserializeOwned>(reader: Reader) -> Result<T, SerdeError> {
+    let mut de = Deserializer {
+        reader,
+        peeked: None,
+    };
+    de.expect(OwnedEvent::StartDocument)?;
+    let OwnedEvent::StartElement(name) = de.next()? else {
+        return Err(de::Error::custom("expected a root element"));
+    };
+    let value = T::deserialize(ElementDeserializer { de: &mut de, name })?;
+    de.expect(OwnedEvent::EndDocument)?;
+    Ok(value)
+}
+
+struct Deserializer<'a> {
+    reader: Reader<'a>,
+    peeked: Option<OwnedEvent>,
+}
+
+impl<'a> Deserializer<'a> {
+    fn next(&mut self) -> Result<OwnedEvent, SerdeError> {
+        if let Some(event) = self.peeked.take() {
+            return Ok(event);
+        }
+        match self.reader.next() {
+            Some(event) => Ok(event?.into()),
+            None => Err(de::Error::custom("unexpected end of stream")),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&OwnedEvent, SerdeError> {
+        let event = match self.peeked.take() {
+            Some(event) => event,
+            None => self.next()?,
+        };
+        Ok(self.peeked.insert(event))
+    }
+
+    fn expect(&mut self, expected: OwnedEvent) -> Result<(), SerdeError> {
+        match self.next()? {
+            event if event == expected => Ok(()),
+            event => Err(de::Error::custom(format!(
+                "expected {:?}, found {:?}",
+                expected, event
+            ))),
+        }
+    }
+
+    /// Skip the rest of an element whose start has been read
+    fn skip_element(&mut self) -> Result<(), SerdeError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                OwnedEvent::StartElement(_) => depth += 1,
+                OwnedEvent::EndElement => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the value of a simple element whose start has been read, up to its end
+    fn leaf(&mut self) -> Result<OwnedValue, SerdeError> {
+        let mut value = None;
+        loop {
+            match self.next()? {
+                OwnedEvent::EndElement => {
+                    return Ok(value.unwrap_or(OwnedValue::String(String::new())))
+                }
+                OwnedEvent::Value(v) if value.is_none() => value = Some(v),
+                // Split character content
+                OwnedEvent::Value(OwnedValue::String(more)) => match &mut value {
+                    Some(OwnedValue::String(text)) => text.push_str(&more),
+                    _ => return Err(de::Error::custom("mixed value types in one element")),
+                },
+                OwnedEvent::StartElement(_) => {
+                    return Err(de::Error::custom("expected a value, found an element"))
+                }
+                // Attributes aren't part of the value
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads an element whose start has been read, through to its end
+struct ElementDeserializer<'d, 'a> {
+    de: &'d mut Deserializer<'a>,
+    name: OwnedName,
+}
+
+impl<'d, 'a> ElementDeserializer<'d, 'a> {
+    fn value(self) -> Result<ValueDeserializer, SerdeError> {
+        Ok(ValueDeserializer(self.de.leaf()?))
+    }
+}
+
+macro_rules! deserialize_leaf {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+                self.value()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'd, 'a> de::Deserializer<'de> for ElementDeserializer<'d, 'a> {
+    type Error = SerdeError;
+
+    /// A map if the element has attributes or child elements, otherwise its value
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        loop {
+            match self.de.peek()? {
+                OwnedEvent::NamespaceDeclaration(_) | OwnedEvent::TypeAttribute(_) => {
+                    self.de.next()?;
+                }
+                OwnedEvent::Attribute(_) | OwnedEvent::StartElement(_) => {
+                    return self.deserialize_map(visitor)
+                }
+                OwnedEvent::EndElement => {
+                    self.de.next()?;
+                    return visitor.visit_unit();
+                }
+                _ => return self.value()?.deserialize_any(visitor),
+            }
+        }
+    }
+
+    deserialize_leaf! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_identifier
+    }
+
+    /// The element is there, so the value is too
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.de.skip_element()?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// An `xs:list` value, or this element and the consecutive siblings with its name
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        if let OwnedEvent::Value(OwnedValue::List(_)) = self.de.peek()? {
+            return self.value()?.deserialize_seq(visitor);
+        }
+        visitor.visit_seq(SiblingAccess {
+            de: self.de,
+            name: self.name,
+            first: true,
+        })
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_map(ChildAccess {
+            de: self.de,
+            fields: &[],
+            pending: None,
+        })
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_map(ChildAccess {
+            de: self.de,
+            fields,
+            pending: None,
+        })
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.value()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_unit(visitor)
+    }
+}
+
+/// Yields an element, then each following sibling with the same name
+struct SiblingAccess<'d, 'a> {
+    de: &'d mut Deserializer<'a>,
+    name: OwnedName,
+    first: bool,
+}
+
+impl<'de, 'd, 'a> de::SeqAccess<'de> for SiblingAccess<'d, 'a> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        if !self.first {
+            match self.de.peek()? {
+                OwnedEvent::StartElement(name) if *name == self.name => {
+                    self.de.next()?;
+                }
+                _ => return Ok(None),
+            }
+        }
+        self.first = false;
+        let name = self.name.clone();
+        seed.deserialize(ElementDeserializer { de: self.de, name })
+            .map(Some)
+    }
+}
+
+enum Pending {
+    Value(OwnedValue),
+    Element(OwnedName),
+}
+
+/// Yields the attributes, child elements and character content of an element as map entries
+struct ChildAccess<'d, 'a> {
+    de: &'d mut Deserializer<'a>,
+    fields: &'static [&'static str],
+    pending: Option<Pending>,
+}
+
+impl<'d, 'a> ChildAccess<'d, 'a> {
+    /// The field an element or attribute fills, preferring one named in Clark notation.
+    /// `prefix` is `@` for attributes.
+    fn key(&self, prefix: &str, name: &OwnedName) -> String {
+        if let Some(ns) = &name.namespace {
+            let clark = format!("{}{{{}}}{}", prefix, ns, name.local_name);
+            if self.fields.contains(&clark.as_str()) {
+                return clark;
+            }
+        }
+        format!("{}{}", prefix, name.local_name)
+    }
+}
+
+impl<'de, 'd, 'a> de::MapAccess<'de> for ChildAccess<'d, 'a> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        let key = loop {
+            match self.de.next()? {
+                OwnedEvent::Attribute(attr) => {
+                    let key = self.key("@", &attr.key);
+                    self.pending = Some(Pending::Value(attr.value));
+                    break key;
+                }
+                OwnedEvent::StartElement(name) => {
+                    let key = self.key("", &name);
+                    self.pending = Some(Pending::Element(name));
+                    break key;
+                }
+                OwnedEvent::Value(value) => {
+                    self.pending = Some(Pending::Value(value));
+                    break "$value".to_string();
+                }
+                OwnedEvent::EndElement => return Ok(None),
+                _ => {}
+            }
+        };
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.pending.take() {
+            Some(Pending::Value(value)) => seed.deserialize(ValueDeserializer(value)),
+            Some(Pending::Element(name)) => {
+                seed.deserialize(ElementDeserializer { de: self.de, name })
+            }
+            None => Err(de::Error::custom("map value without a key")),
+        }
+    }
+}
+
+/// Deserializes a single value
+struct ValueDeserializer(OwnedValue);
+
+impl<'de> IntoDeserializer<'de, SerdeError> for ValueDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Deserialize hints that parse strings, falling back on the value's own type
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty,)*) => {
+        $(
+            fn $method<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+                match self.0 {
+                    OwnedValue::String(s) => match s.trim().parse::<$ty>() {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(&s), &visitor)),
+                    },
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            OwnedValue::Integer(int) => visitor.visit_i64(int),
+            OwnedValue::Boolean(bool) => visitor.visit_bool(bool),
+            OwnedValue::String(str) => visitor.visit_string(str),
+            OwnedValue::Float(fl) | OwnedValue::Decimal(fl) => visitor.visit_f64(fl),
+            OwnedValue::Binary(bin) => visitor.visit_byte_buf(bin.to_vec()),
+            // The lexical form chrono's types deserialize from
+            OwnedValue::Timestamp(ts) => visitor.visit_string(Value::Timestamp(&ts).to_string()),
+            OwnedValue::List(list) => {
+                let mut seq = SeqDeserializer::new(list.into_iter().map(ValueDeserializer));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            OwnedValue::QName(name) => visitor.visit_string(name.as_name().local_name.to_string()),
+        }
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            OwnedValue::String(s) => match s.trim() {
+                "true" | "1" => visitor.visit_bool(true),
+                "false" | "0" => visitor.visit_bool(false),
+                _ => Err(de::Error::invalid_value(de::Unexpected::Str(&s), &visitor)),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i64: i64,
+        deserialize_i16 => visit_i64: i64,
+        deserialize_i32 => visit_i64: i64,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u64: u64,
+        deserialize_u16 => visit_u64: u64,
+        deserialize_u32 => visit_u64: u64,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f64: f64,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Strings split on whitespace, as in schemaless `xs:list`s
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            OwnedValue::String(s) => {
+                let items = s
+                    .split_whitespace()
+                    .map(|item| ValueDeserializer(OwnedValue::String(item.to_string())));
+                let mut seq = SeqDeserializer::new(items);
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    /// Unit variants, by name
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            OwnedValue::String(s) => visitor.visit_enum(s.into_deserializer()),
+            other => Err(de::Error::custom(format!(
+                "expected an enum variant name, found {:?}",
+                other
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[test]
+fn schemaless_serialize() {
+    #[derive(::serde::Serialize)]
+    struct Order {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@tags")]
+        tags: Vec<&'static str>,
+        customer: &'static str,
+        item: Vec<Item>,
+        note: Option<&'static str>,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct Item {
+        #[serde(rename = "@sku")]
+        sku: &'static str,
+        #[serde(rename = "$value")]
+        quantity: u8,
+    }
+
+    let order = Order {
+        id: 7,
+        tags: vec!["new", "priority"],
+        customer: "Ada",
+        item: vec![
+            Item {
+                sku: "A1",
+                quantity: 2,
+            },
+            Item {
+                sku: "B2",
+                quantity: 1,
+            },
+        ],
+        note: None,
+    };
+    let encoded = to_bytes(&order, Header::default(), None).unwrap();
+
+    let mut writer = Writer::default();
+    writer
+        .document(|d| {
+            d.element("Order", |e| {
+                e.attr("id", Value::Integer(7))?;
+                e.attr("tags", Value::String("new priority"))?;
+                e.element("customer", |c| c.text("Ada"))?;
+                e.element("item", |i| {
+                    i.attr("sku", Value::String("A1"))?;
+                    i.value(Value::Integer(2))
+                })?;
+                e.element("item", |i| {
+                    i.attr("sku", Value::String("B2"))?;
+                    i.value(Value::Integer(1))
+                })
+            })
+        })
+        .unwrap();
+    assert_eq!(encoded, writer.get());
+}
+
+#[test]
+fn schema_serialize() {
+    use crate::config::Options;
+    use crate::testing::{example_schema, FULL_DOCUMENT};
+
+    #[derive(::serde::Serialize)]
+    struct MultipleXSDsTest {
+        #[serde(rename = "EXIPEncoder")]
+        encoder: Encoder,
+        description: &'static str,
+        #[serde(rename = "{http://www.ltu.se/EISLAB/nested-xsd}testSetup")]
+        test_setup: TestSetup,
+        #[serde(rename = "type-test")]
+        type_test: TypeTest,
+        #[serde(rename = "extendedTypeTest")]
+        extended: Extended,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct Encoder {
+        #[serde(rename = "@testByte")]
+        test_byte: i8,
+        #[serde(rename = "@version")]
+        version: &'static str,
+        #[serde(rename = "$value")]
+        text: &'static str,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct TestSetup {
+        #[serde(rename = "@goal")]
+        goal: &'static str,
+        #[serde(rename = "$value")]
+        text: &'static str,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct TypeTest {
+        #[serde(rename = "@id")]
+        id: i32,
+        #[serde(rename = "{http://www.ltu.se/EISLAB/nested-xsd}bool")]
+        flag: bool,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct Extended {
+        #[serde(rename = "{}byteTest")]
+        byte: i8,
+        #[serde(rename = "{}dateTimeTest")]
+        date_time: &'static str,
+        #[serde(rename = "{}binaryTest")]
+        binary: Binary,
+        #[serde(rename = "{}enumTest")]
+        enumeration: Enumeration,
+    }
+
+    struct Binary(&'static [u8]);
+
+    impl ::serde::Serialize for Binary {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(self.0)
+        }
+    }
+
+    #[derive(::serde::Serialize)]
+    enum Enumeration {
+        #[serde(rename = "hej")]
+        Hej,
+    }
+
+    let value = MultipleXSDsTest {
+        encoder: Encoder {
+            test_byte: 55,
+            version: "0.2",
+            text: "This is an example of serializing EXI streams using EXIP low level API",
+        },
+        description: "This is a test of processing XML schemes with multiple XSD files",
+        test_setup: TestSetup {
+            goal: "Verify that the implementation works!",
+            text: "Simple test element with single attribute",
+        },
+        type_test: TypeTest {
+            id: 1001,
+            flag: true,
+        },
+        extended: Extended {
+            byte: 11,
+            date_time: "2012-07-31T13:33:55.000839",
+            binary: Binary(&[0x02, 0x6d, 0x2f, 0xa5, 0x20, 0xf2, 0x61, 0x9c, 0xee, 0x0f]),
+            enumeration: Enumeration::Hej,
+        },
+    };
+
+    let options = Options::default().strict(true);
+    let header = Header::with_options(options).has_cookie(true);
+    let schema = example_schema(None);
+    let mut writer = Writer::new(header, Some(schema)).unwrap();
+    writer.add(Event::StartDocument).unwrap();
+    value
+        .serialize(
+            &mut Serializer::new(&mut writer)
+                .with_namespace("http://www.ltu.se/EISLAB/schema-test"),
+        )
+        .unwrap();
+    writer.add(Event::EndDocument).unwrap();
+    // As written event by event in writer::full_write
+    assert_eq!(writer.get(), FULL_DOCUMENT);
+}
+
+#[test]
+fn schemaless_deserialize() {
+    #[derive(::serde::Serialize, ::serde::Deserialize, Debug, PartialEq)]
+    struct Order {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@tags")]
+        tags: Vec<String>,
+        customer: String,
+        item: Vec<Item>,
+        note: Option<String>,
+        status: Status,
+    }
+
+    #[derive(::serde::Serialize, ::serde::Deserialize, Debug, PartialEq)]
+    struct Item {
+        #[serde(rename = "@sku")]
+        sku: String,
+        #[serde(rename = "$value")]
+        quantity: u8,
+    }
+
+    #[derive(::serde::Serialize, ::serde::Deserialize, Debug, PartialEq)]
+    enum Status {
+        Open,
+        Closed,
+    }
+
+    let order = Order {
+        id: 7,
+        tags: vec!["new".into(), "priority".into()],
+        customer: "Ada".into(),
+        item: vec![
+            Item {
+                sku: "A1".into(),
+                quantity: 2,
+            },
+            Item {
+                sku: "B2".into(),
+                quantity: 1,
+            },
+        ],
+        note: None,
+        status: Status::Closed,
+    };
+    let encoded = to_bytes(&order, Header::default(), None).unwrap();
+    assert_eq!(from_bytes::<Order>(encoded, None).unwrap(), order);
+}
+
+#[test]
+fn namespaced_attributes() {
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct Root {
+        #[serde(rename = "@id")]
+        id: String,
+        #[serde(rename = "@{urn:x}id")]
+        x_id: String,
+    }
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("root", |e| {
+                e.attr("id", Value::String("plain"))?;
+                e.attr(
+                    Name {
+                        local_name: "id",
+                        namespace: Some("urn:x"),
+                        prefix: None,
+                    },
+                    Value::String("x"),
+                )
+            })
+        })
+        .unwrap();
+    assert_eq!(
+        from_bytes::<Root>(writer.get().to_vec(), None).unwrap(),
+        Root {
+            id: "plain".into(),
+            x_id: "x".into(),
+        }
+    );
+}
+
+#[test]
+fn timestamp_round_trip() {
+    use crate::config::Options;
+    use crate::testing::example_schema;
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+    #[derive(::serde::Serialize)]
+    struct MultipleXSDsTest {
+        #[serde(rename = "EXIPEncoder")]
+        encoder: Encoder,
+        description: &'static str,
+        #[serde(rename = "{http://www.ltu.se/EISLAB/nested-xsd}testSetup")]
+        test_setup: Encoder,
+        #[serde(rename = "type-test")]
+        type_test: TypeTest,
+        #[serde(rename = "extendedTypeTest")]
+        extended: Extended,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct Encoder {
+        #[serde(rename = "@testByte", skip_serializing_if = "Option::is_none")]
+        test_byte: Option<i8>,
+        #[serde(rename = "@goal", skip_serializing_if = "Option::is_none")]
+        goal: Option<&'static str>,
+        #[serde(rename = "@version", skip_serializing_if = "Option::is_none")]
+        version: Option<&'static str>,
+        #[serde(rename = "$value")]
+        text: &'static str,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct TypeTest {
+        #[serde(rename = "@id")]
+        id: i32,
+        #[serde(rename = "{http://www.ltu.se/EISLAB/nested-xsd}bool")]
+        flag: bool,
+    }
+
+    #[derive(::serde::Serialize)]
+    struct Extended {
+        #[serde(rename = "{}byteTest")]
+        byte: i8,
+        #[serde(rename = "{}dateTimeTest")]
+        date_time: NaiveDateTime,
+        #[serde(rename = "{}binaryTest")]
+        binary: &'static str,
+        #[serde(rename = "{}enumTest")]
+        enumeration: &'static str,
+    }
+
+    // Read back with only the timestamp
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct Root {
+        #[serde(rename = "extendedTypeTest")]
+        extended: Timestamp,
+    }
+
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct Timestamp {
+        #[serde(rename = "dateTimeTest")]
+        date_time: NaiveDateTime,
+    }
+
+    let date_time = NaiveDateTime::new(
+        NaiveDate::from_ymd_opt(2012, 7, 31).unwrap(),
+        NaiveTime::from_hms_micro_opt(13, 33, 55, 839).unwrap(),
+    );
+    let value = MultipleXSDsTest {
+        encoder: Encoder {
+            test_byte: Some(55),
+            goal: None,
+            version: Some("0.2"),
+            text: "text",
+        },
+        description: "description",
+        test_setup: Encoder {
+            test_byte: None,
+            goal: Some("goal"),
+            version: None,
+            text: "setup",
+        },
+        type_test: TypeTest {
+            id: 1001,
+            flag: true,
+        },
+        extended: Extended {
+            byte: 11,
+            date_time,
+            binary: "Am0vpSDyYZzuDw==",
+            enumeration: "hej",
+        },
+    };
+
+    let schema = || example_schema(None);
+    let options = Options::default().strict(true);
+    let mut writer = Writer::new(Header::with_options(options), Some(schema())).unwrap();
+    writer.add(Event::StartDocument).unwrap();
+    value
+        .serialize(
+            &mut Serializer::new(&mut writer)
+                .with_namespace("http://www.ltu.se/EISLAB/schema-test"),
+        )
+        .unwrap();
+    writer.add(Event::EndDocument).unwrap();
+
+    // Written as a typed dateTime, not a string
+    let reader = Reader::new(writer.get().to_vec(), Some(schema()), None).unwrap();
+    let events: Vec<OwnedEvent> = reader.map(|e| e.unwrap().into()).collect();
+    assert!(events.contains(&OwnedEvent::Value(OwnedValue::Timestamp(date_time))));
+
+    let root: Root = from_bytes(writer.get().to_vec(), Some(schema())).unwrap();
+    assert_eq!(root.extended.date_time, date_time);
+}
+
+#[test]
+fn typed_deserialize() {
+    use crate::testing::{example_document, example_schema};
+
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct MultipleXSDsTest {
+        #[serde(rename = "EXIPEncoder")]
+        encoder: Encoder,
+        #[serde(rename = "type-test")]
+        type_test: TypeTest,
+        #[serde(rename = "extendedTypeTest")]
+        extended: Extended,
+    }
+
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct Encoder {
+        #[serde(rename = "@testByte")]
+        test_byte: i8,
+        #[serde(rename = "@version")]
+        version: String,
+    }
+
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct TypeTest {
+        #[serde(rename = "@id")]
+        id: i32,
+        #[serde(rename = "{http://www.ltu.se/EISLAB/nested-xsd}bool")]
+        flag: bool,
+    }
+
+    #[derive(::serde::Deserialize, Debug, PartialEq)]
+    struct Extended {
+        #[serde(rename = "byteTest")]
+        byte: i8,
+        #[serde(rename = "dateTimeTest")]
+        date_time: String,
+        #[serde(rename = "binaryTest", deserialize_with = "bytes")]
+        binary: Vec<u8>,
+    }
+
+    fn bytes<'de, D: de::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        struct Bytes;
+
+        impl<'de> de::Visitor<'de> for Bytes {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(v)
+            }
+        }
+
+        d.deserialize_byte_buf(Bytes)
+    }
+
+    let bytes = example_document();
+
+    let value: MultipleXSDsTest = from_bytes(bytes, Some(example_schema(None))).unwrap();
+    assert_eq!(
+        value,
+        MultipleXSDsTest {
+            encoder: Encoder {
+                test_byte: 55,
+                version: "0.2".into(),
+            },
+            type_test: TypeTest {
+                id: 1001,
+                flag: true,
+            },
+            extended: Extended {
+                byte: 11,
+                date_time: "2012-07-31T13:33:55.000839".into(),
+                binary: vec![0x02, 0x6d, 0x2f],
+            },
+        }
+    );
+}
@@ -0,0 +1,93 @@
+//! Fixtures shared by the tests
+
+use bytes::Bytes;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::{
+    config::{Header, Options, Schema},
+    data::{Name, Value},
+    Writer,
+};
+
+/// The schema from EXIP's `exipe-test` example, whose documents the tests encode
+pub(crate) fn example_schema(opts: Option<Options>) -> Schema {
+    Schema::new(
+        &[
+            "./examples/exipe-test-xsd.exi",
+            "./examples/exipe-test-types-xsd.exi",
+            "./examples/exipe-test-nested-xsd.exi",
+        ],
+        opts,
+    )
+    .unwrap()
+}
+
+/// The document `writer::full_write` builds event by event, with `example_schema` in strict
+/// mode and a cookie, as EXIP encodes it
+pub(crate) const FULL_DOCUMENT: &[u8] = &[
+    0x24, 0x45, 0x58, 0x49, 0xA0, 0x49, 0x6E, 0x05, 0x30, 0x2E, 0x32, 0x48, 0x54, 0x68, 0x69, 0x73,
+    0x20, 0x69, 0x73, 0x20, 0x61, 0x6E, 0x20, 0x65, 0x78, 0x61, 0x6D, 0x70, 0x6C, 0x65, 0x20, 0x6F,
+    0x66, 0x20, 0x73, 0x65, 0x72, 0x69, 0x61, 0x6C, 0x69, 0x7A, 0x69, 0x6E, 0x67, 0x20, 0x45, 0x58,
+    0x49, 0x20, 0x73, 0x74, 0x72, 0x65, 0x61, 0x6D, 0x73, 0x20, 0x75, 0x73, 0x69, 0x6E, 0x67, 0x20,
+    0x45, 0x58, 0x49, 0x50, 0x20, 0x6C, 0x6F, 0x77, 0x20, 0x6C, 0x65, 0x76, 0x65, 0x6C, 0x20, 0x41,
+    0x50, 0x49, 0x21, 0x2A, 0x34, 0x34, 0xB9, 0x90, 0x34, 0xB9, 0x90, 0x30, 0x90, 0x3A, 0x32, 0xB9,
+    0xBA, 0x10, 0x37, 0xB3, 0x10, 0x38, 0x39, 0x37, 0xB1, 0xB2, 0xB9, 0xB9, 0xB4, 0xB7, 0x33, 0x90,
+    0x2C, 0x26, 0xA6, 0x10, 0x39, 0xB1, 0xB4, 0x32, 0xB6, 0xB2, 0xB9, 0x90, 0x3B, 0xB4, 0xBA, 0x34,
+    0x10, 0x36, 0xBA, 0xB6, 0x3A, 0x34, 0xB8, 0x36, 0x32, 0x90, 0x2C, 0x29, 0xA2, 0x10, 0x33, 0x34,
+    0xB6, 0x32, 0xB9, 0x89, 0xD5, 0x99, 0x5C, 0x9A, 0x59, 0x9E, 0x48, 0x1D, 0x1A, 0x18, 0x5D, 0x08,
+    0x1D, 0x1A, 0x19, 0x48, 0x1A, 0x5B, 0x5C, 0x1B, 0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x18, 0x5D, 0x1A,
+    0x5B, 0xDB, 0x88, 0x1D, 0xDB, 0xDC, 0x9A, 0xDC, 0xC8, 0x4A, 0xD4, 0xDA, 0x5B, 0x5C, 0x1B, 0x19,
+    0x48, 0x1D, 0x19, 0x5C, 0xDD, 0x08, 0x19, 0x5B, 0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x08, 0x1D, 0xDA,
+    0x5D, 0x1A, 0x08, 0x1C, 0xDA, 0x5B, 0x99, 0xDB, 0x19, 0x48, 0x18, 0x5D, 0x1D, 0x1C, 0x9A, 0x58,
+    0x9D, 0x5D, 0x19, 0x47, 0x48, 0x3A, 0x8B, 0x06, 0x3F, 0xDB, 0x0E, 0xF9, 0x0A, 0x03, 0x90, 0x14,
+    0x04, 0xDA, 0x5F, 0x4A, 0x41, 0xE4, 0xC3, 0x39, 0xDC, 0x1E, 0xC0,
+];
+
+/// A document for `example_schema` with a value of most types, written with the scoped
+/// builder in strict mode
+pub(crate) fn example_document() -> Vec<u8> {
+    let n = |local_name| Name {
+        local_name,
+        namespace: Some("http://www.ltu.se/EISLAB/schema-test"),
+        prefix: None,
+    };
+    let nested = |local_name| Name {
+        local_name,
+        namespace: Some("http://www.ltu.se/EISLAB/nested-xsd"),
+        prefix: None,
+    };
+    let date = NaiveDate::from_ymd_opt(2012, 7, 31).unwrap();
+    let time = NaiveTime::from_hms_micro_opt(13, 33, 55, 839).unwrap();
+    let timestamp = NaiveDateTime::new(date, time);
+    let header = Header::with_options(Options::default().strict(true));
+    let mut writer = Writer::new(header, Some(example_schema(None))).unwrap();
+    writer
+        .document(|d| {
+            d.element(n("MultipleXSDsTest"), |e| {
+                e.element(n("EXIPEncoder"), |e| {
+                    e.attr("testByte", Value::Integer(55))?;
+                    e.attr("version", Value::String("0.2"))?;
+                    e.text("text")
+                })?;
+                e.element(n("description"), |e| e.text("description"))?;
+                e.element(nested("testSetup"), |e| {
+                    e.attr("goal", Value::String("goal"))?;
+                    e.text("setup")
+                })?;
+                e.element(n("type-test"), |e| {
+                    e.attr("id", Value::Integer(1001))?;
+                    e.element(nested("bool"), |e| e.value(Value::Boolean(true)))
+                })?;
+                e.element(n("extendedTypeTest"), |e| {
+                    e.element("byteTest", |e| e.value(Value::Integer(11)))?;
+                    e.element("dateTimeTest", |e| e.value(Value::Timestamp(&timestamp)))?;
+                    e.element("binaryTest", |e| {
+                        e.value(Value::Binary(Bytes::from_static(&[0x02, 0x6d, 0x2f])))
+                    })?;
+                    e.element("enumTest", |e| e.text("hej"))
+                })
+            })
+        })
+        .unwrap();
+    writer.get().to_vec()
+}
@@ -0,0 +1,420 @@
+//! Where each event sits in a stream, and the grammar production it was decoded with, for
+//! finding where two implementations disagree. Enabled with `Reader::set_tracing`.
+
+use std::fmt::{self, Display, Write as _};
+
+use crate::{
+    data::{Event, OwnedEvent},
+    schema::table,
+};
+
+/// How an event was encoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    /// Offset of the event's first bit from the start of the stream, header included
+    pub bit_offset: usize,
+    /// Bits taken by the event code and content
+    pub bit_length: usize,
+    /// The non-terminal of the grammar rule the event was decoded in
+    pub non_terminal: Option<usize>,
+    /// The rule's production the event matched. Undeclared productions are only known for
+    /// built-in element grammars, as in schemaless streams.
+    pub production: Option<Production>,
+}
+
+/// A production of a grammar rule
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Production {
+    /// The event, in the notation of the EXI specification, e.g. `SE(qname)`
+    pub event: &'static str,
+    /// The event code, one part per level, e.g. `[1, 2]` for the second level code `1.2`
+    pub event_code: Vec<usize>,
+    /// First-level productions in the rule, which with any undeclared productions decides the
+    /// width of the event code
+    pub productions: usize,
+    /// The non-terminal the production leads to, or `None` if it ends the element or document
+    pub next: Option<usize>,
+}
+
+impl Display for Production {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.event)?;
+        for (i, part) in self.event_code.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", part)?;
+        }
+        write!(f, "/{}", self.productions)?;
+        match self.next {
+            Some(next) => write!(f, " -> {}", next),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The options deciding which undeclared productions built-in element grammars have
+#[derive(Clone, Copy)]
+pub(crate) struct Undeclared {
+    pub(crate) prefixes: bool,
+    pub(crate) self_contained: bool,
+}
+
+// Non-terminals of built-in element grammars
+const START_TAG_CONTENT: usize = 0;
+const ELEMENT_CONTENT: usize = 1;
+
+/// The grammar rule in effect before an event, copied as built-in grammars grow while parsing
+pub(crate) struct Rule {
+    non_terminal: usize,
+    productions: Vec<ffi::Production>,
+    /// Set in built-in element grammars
+    undeclared: Option<Undeclared>,
+}
+
+impl Rule {
+    /// `undeclared` is given when element grammars are built-in, as in schemaless streams
+    ///
+    /// # Safety
+    ///
+    /// `strm` must have been initialised by EXIP, not yet destroyed, and not be in use by EXIP,
+    /// as between calls to `parseNext`. Its grammar stack, the grammars on it, and their rule
+    /// and production tables are then valid and unchanged until EXIP next runs, and the
+    /// productions are copied before returning.
+    pub(crate) unsafe fn current(
+        strm: &ffi::EXIStream,
+        undeclared: Option<Undeclared>,
+    ) -> Option<Rule> {
+        // SAFETY: EXIP's grammar stack and each entry's grammar are null or valid, per the
+        // precondition
+        let stack = unsafe { strm.gStack.as_ref()? };
+        let grammar = unsafe { stack.grammar.as_ref()? };
+        let non_terminal = strm.context.currNonTermID as usize;
+        // SAFETY: a grammar's `rule` table has `count` rules, and each rule's `production`
+        // table `pCount` productions, which EXIP doesn't change while it isn't running
+        let rule = unsafe { table(grammar.rule, grammar.count as usize) }.get(non_terminal)?;
+        Some(Rule {
+            non_terminal,
+            productions: unsafe { table(rule.production, rule.pCount) }.to_vec(),
+            // The document or fragment grammar is at the bottom of the stack
+            undeclared: undeclared.filter(|_| !stack.nextInStack.is_null()),
+        })
+    }
+
+    /// The event types of the rule's second level productions, in event code order, with the
+    /// non-terminals they lead to, as in section 8.4.3 of the EXI specification. Those for
+    /// entity references, comments and processing instructions come last, and are left out as
+    /// they aren't read as events.
+    fn second_level(&self) -> Vec<(u32, Option<usize>)> {
+        let Some(undeclared) = self.undeclared else {
+            return vec![];
+        };
+        match self.non_terminal {
+            START_TAG_CONTENT => {
+                let mut level = vec![
+                    (ffi::EventType_EVENT_EE, None),
+                    (ffi::EventType_EVENT_AT_ALL, Some(START_TAG_CONTENT)),
+                ];
+                if undeclared.prefixes {
+                    level.push((ffi::EventType_EVENT_NS, Some(START_TAG_CONTENT)));
+                }
+                if undeclared.self_contained {
+                    level.push((ffi::EventType_EVENT_SC, None));
+                }
+                level.push((ffi::EventType_EVENT_SE_ALL, Some(ELEMENT_CONTENT)));
+                level.push((ffi::EventType_EVENT_CH, Some(ELEMENT_CONTENT)));
+                level
+            }
+            ELEMENT_CONTENT => vec![
+                (ffi::EventType_EVENT_SE_ALL, Some(ELEMENT_CONTENT)),
+                (ffi::EventType_EVENT_CH, Some(ELEMENT_CONTENT)),
+            ],
+            _ => vec![],
+        }
+    }
+}
+
+impl Trace {
+    pub(crate) fn new(
+        bit_offset: usize,
+        strm: &ffi::EXIStream,
+        rule: Option<Rule>,
+        event: &Event,
+    ) -> Self {
+        let end = strm.context.bufferIndx * 8 + strm.context.bitPointer as usize;
+        let production = rule.as_ref().and_then(|rule| {
+            let count = rule.productions.len();
+            // EXIP stores productions in reverse, so the last has event code 0
+            let first = (0..count).find_map(|event_code| {
+                let prod = &rule.productions[count - 1 - event_code];
+                let event_type = prod.content >> 24;
+                matches(prod, event, &strm.context).then(|| Production {
+                    event: event_name(event_type),
+                    event_code: vec![event_code],
+                    productions: count,
+                    // GET_PROD_NON_TERM in EXIP
+                    next: (!ends(event_type)).then_some((prod.content & 0x00FF_FFFF) as usize),
+                })
+            });
+            // Second level codes follow the first level ones
+            first.or_else(|| {
+                let second = rule.second_level();
+                let (i, (event_type, next)) = second
+                    .into_iter()
+                    .enumerate()
+                    .find(|(_, (event_type, _))| matches_undeclared(*event_type, event))?;
+                Some(Production {
+                    event: event_name(event_type),
+                    event_code: vec![count, i],
+                    productions: count,
+                    next,
+                })
+            })
+        });
+        Trace {
+            bit_offset,
+            bit_length: end.saturating_sub(bit_offset),
+            non_terminal: rule.map(|rule| rule.non_terminal),
+            production,
+        }
+    }
+}
+
+/// Whether `event`, just decoded, came from `prod`
+fn matches(prod: &ffi::Production, event: &Event, ctx: &ffi::StreamContext) -> bool {
+    let id = prod.qnameId;
+    let attribute = matches!(event, Event::Attribute(_) | Event::TypeAttribute(_));
+    match prod.content >> 24 {
+        ffi::EventType_EVENT_SD => *event == Event::StartDocument,
+        ffi::EventType_EVENT_ED => *event == Event::EndDocument,
+        ffi::EventType_EVENT_EE => *event == Event::EndElement,
+        ffi::EventType_EVENT_SE_QNAME => {
+            matches!(event, Event::StartElement(_))
+                && (id.uriId, id.lnId) == (ctx.currElem.uriId, ctx.currElem.lnId)
+        }
+        ffi::EventType_EVENT_SE_URI => {
+            matches!(event, Event::StartElement(_)) && id.uriId == ctx.currElem.uriId
+        }
+        ffi::EventType_EVENT_SE_ALL => matches!(event, Event::StartElement(_)),
+        ffi::EventType_EVENT_AT_QNAME => {
+            attribute && (id.uriId, id.lnId) == (ctx.currAttr.uriId, ctx.currAttr.lnId)
+        }
+        ffi::EventType_EVENT_AT_URI => attribute && id.uriId == ctx.currAttr.uriId,
+        ffi::EventType_EVENT_AT_ALL => attribute,
+        ffi::EventType_EVENT_CH => matches!(event, Event::Value(_)),
+        ffi::EventType_EVENT_NS => matches!(event, Event::NamespaceDeclaration(_)),
+        _ => false,
+    }
+}
+
+/// Whether `event`, just decoded, came from an undeclared production of `event_type`
+fn matches_undeclared(event_type: u32, event: &Event) -> bool {
+    match event_type {
+        ffi::EventType_EVENT_EE => *event == Event::EndElement,
+        ffi::EventType_EVENT_AT_ALL => {
+            matches!(event, Event::Attribute(_) | Event::TypeAttribute(_))
+        }
+        ffi::EventType_EVENT_NS => matches!(event, Event::NamespaceDeclaration(_)),
+        ffi::EventType_EVENT_SE_ALL => matches!(event, Event::StartElement(_)),
+        ffi::EventType_EVENT_CH => matches!(event, Event::Value(_)),
+        _ => false,
+    }
+}
+
+/// Whether productions of `event_type` end the grammar, rather than leading to a non-terminal
+fn ends(event_type: u32) -> bool {
+    matches!(
+        event_type,
+        ffi::EventType_EVENT_EE | ffi::EventType_EVENT_ED
+    )
+}
+
+fn event_name(event_type: u32) -> &'static str {
+    match event_type {
+        ffi::EventType_EVENT_SD => "SD",
+        ffi::EventType_EVENT_ED => "ED",
+        ffi::EventType_EVENT_SE_QNAME => "SE(qname)",
+        ffi::EventType_EVENT_SE_URI => "SE(uri:*)",
+        ffi::EventType_EVENT_SE_ALL => "SE(*)",
+        ffi::EventType_EVENT_EE => "EE",
+        ffi::EventType_EVENT_AT_QNAME => "AT(qname)",
+        ffi::EventType_EVENT_AT_URI => "AT(uri:*)",
+        ffi::EventType_EVENT_AT_ALL => "AT(*)",
+        ffi::EventType_EVENT_CH => "CH",
+        ffi::EventType_EVENT_NS => "NS",
+        ffi::EventType_EVENT_CM => "CM",
+        ffi::EventType_EVENT_PI => "PI",
+        ffi::EventType_EVENT_DT => "DT",
+        ffi::EventType_EVENT_ER => "ER",
+        ffi::EventType_EVENT_SC => "SC",
+        _ => "?",
+    }
+}
+
+/// Bytes shown per event, before the rest are elided
+const DUMP_BYTES: usize = 8;
+
+/// Render traced events as a hex dump, one line per event: the bit range, the bytes it falls
+/// in, the rule and production, and the event.
+///
+/// ```text
+///        0+8  80                        header
+///        8+0                            0: SD 0/1 -> 1       StartDocument
+///       8+14  40 d4                     1: SE(*) 0/1 -> 2    StartElement(...)
+///      22+13  d4 4e                     0: AT(*) 0.1/0 -> 0  Attribute(...)
+/// ```
+pub fn hex_dump(bytes: &[u8], events: &[(OwnedEvent, Trace)]) -> String {
+    let mut out = String::new();
+    if let Some((_, first)) = events.first() {
+        line(&mut out, bytes, 0, first.bit_offset, "header");
+    }
+    for (event, trace) in events {
+        let production = match (trace.non_terminal, &trace.production) {
+            (Some(rule), Some(production)) => format!("{}: {}", rule, production),
+            (Some(rule), None) => format!("{}: undeclared", rule),
+            (None, _) => String::new(),
+        };
+        let annotation = format!("{:<20} {:?}", production, event);
+        line(
+            &mut out,
+            bytes,
+            trace.bit_offset,
+            trace.bit_length,
+            &annotation,
+        );
+    }
+    out
+}
+
+fn line(out: &mut String, bytes: &[u8], offset: usize, len: usize, annotation: &str) {
+    let span = match len {
+        0 => &[][..],
+        _ => {
+            let end = (offset + len).div_ceil(8).min(bytes.len());
+            bytes.get(offset / 8..end).unwrap_or_default()
+        }
+    };
+    let mut hex = String::new();
+    for byte in span.iter().take(DUMP_BYTES) {
+        write!(hex, "{:02x} ", byte).expect("writing to a string");
+    }
+    if span.len() > DUMP_BYTES {
+        hex.push_str("..");
+    }
+    let range = format!("{}+{}", offset, len);
+    writeln!(out, "{:>10}  {:<26}{}", range, hex, annotation.trim_end())
+        .expect("writing to a string");
+}
+
+#[test]
+fn schemaless_trace() {
+    use crate::{config::Header, data::Value, Reader, Writer};
+
+    let mut writer = Writer::new(Header::default(), None).unwrap();
+    writer
+        .document(|d| {
+            d.element("order", |e| {
+                e.attr("id", Value::String("7"))?;
+                e.element("item", |e| e.text("2"))?;
+                e.element("item", |e| e.text("3"))?;
+                e.element("item", |e| e.text("5"))
+            })
+        })
+        .unwrap();
+    let bytes = writer.get().to_vec();
+
+    let mut reader = Reader::new(bytes.clone(), None, None).unwrap();
+    reader.set_tracing(true);
+    let mut events = vec![];
+    while let Some(event) = reader.next() {
+        let event: OwnedEvent = event.unwrap().into();
+        events.push((event, reader.trace().unwrap().clone()));
+    }
+
+    // The events cover the stream, after the 8 bit header
+    let mut offset = 8;
+    for (_, trace) in &events {
+        assert_eq!(trace.bit_offset, offset);
+        offset += trace.bit_length;
+    }
+    assert_eq!(offset.div_ceil(8), bytes.len());
+
+    let production = |i: usize| events[i].1.production.as_ref().unwrap();
+    assert_eq!(production(0).event, "SD");
+    assert_eq!(events[0].1.bit_length, 0);
+    // `order` starts with no first level productions, so its attribute is matched by the
+    // second level `AT(*)`, after `EE`
+    assert!(matches!(events[2].0, OwnedEvent::Attribute(_)));
+    assert_eq!(
+        production(2),
+        &Production {
+            event: "AT(*)",
+            event_code: vec![0, 1],
+            productions: 0,
+            next: Some(START_TAG_CONTENT),
+        }
+    );
+    // The first `item` is in the start tag of `order`, but the second is the first element in
+    // its content, where only `EE` has a first level code. So it's matched by the second
+    // level `SE(*)`, which learns a first level `SE(qname)` with event code 0.
+    assert_eq!(events[6].0, OwnedEvent::StartElement("item".into()));
+    assert_eq!(
+        production(6),
+        &Production {
+            event: "SE(*)",
+            event_code: vec![1, 0],
+            productions: 1,
+            next: Some(ELEMENT_CONTENT),
+        }
+    );
+    assert_eq!(events[9].0, OwnedEvent::StartElement("item".into()));
+    let learned = production(9);
+    assert_eq!(
+        (learned.event, &learned.event_code[..]),
+        ("SE(qname)", &[0][..])
+    );
+
+    let dump = hex_dump(&bytes, &events);
+    assert_eq!(dump.lines().count(), events.len() + 1);
+    assert!(dump.lines().next().unwrap().ends_with("header"), "{}", dump);
+}
+
+#[test]
+fn schema_trace() {
+    use crate::testing::example_schema;
+    use crate::{
+        config::{Header, Options},
+        data::{Name, Value},
+        Reader, Writer,
+    };
+
+    let schema = || example_schema(None);
+    let header = Header::with_options(Options::default().strict(true));
+    let mut writer = Writer::new(header, Some(schema())).unwrap();
+    writer
+        .document(|d| {
+            d.element(
+                Name {
+                    local_name: "bool",
+                    namespace: Some("http://www.ltu.se/EISLAB/nested-xsd"),
+                    prefix: None,
+                },
+                |e| e.value(Value::Boolean(true)),
+            )
+        })
+        .unwrap();
+    let bytes = writer.get().to_vec();
+
+    let mut reader = Reader::new(bytes, Some(schema()), None).unwrap();
+    reader.set_tracing(true);
+    let mut traces = vec![];
+    while let Some(event) = reader.next() {
+        event.unwrap();
+        traces.push(reader.trace().unwrap().clone());
+    }
+    // Strict streams only use first-level event codes
+    assert!(traces.iter().all(|trace| trace.production.is_some()));
+    assert_eq!(traces[2].production.as_ref().unwrap().event, "CH");
+    // The boolean takes a bit
+    assert!(traces[2].bit_length >= 1);
+}
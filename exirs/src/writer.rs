@@ -1,30 +1,63 @@
 use std::mem::MaybeUninit;
 
+use base64::Engine;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
 use crate::{
-    config::{Header, Schema},
+    config::{Header, Options, Schema, SchemaRef},
     data::{to_stringtype, Attribute, Event, Name, NamespaceDeclaration, Value},
-    error::EXIPError,
+    error::{check, EXIPError, Error, ErrorKind, Operation, SequenceError},
+    schema::{simple_type, table, type_class, TypeClass},
     to_qname,
 };
 
 const OUTPUT_BUFFER_SIZE: usize = 8 * 1024;
 
-pub struct Writer {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DocumentState {
+    NotStarted,
+    Started,
+    Ended,
+}
+
+pub struct Writer<'a> {
     uses_schema: bool,
     cur_tc: Box<ffi::EXITypeClass>,
     stream: Box<ffi::EXIStream>,
     _buf: Box<[u8]>,
+    // Referenced by the stream for as long as it's open
+    _schema: Option<SchemaRef<'a>>,
+    // Number of events added so far, and the local names of the open elements, as `/a/b`,
+    // kept in one buffer so elements don't each allocate
+    events: usize,
+    path: String,
+    // Tracked to reject event sequences EXIP would choke on
+    document: DocumentState,
+    fragment: bool,
+    has_root: bool,
+    // Whether the innermost open element has child elements or values yet
+    has_content: bool,
 }
 
-impl Drop for Writer {
+impl<'a> Drop for Writer<'a> {
     fn drop(&mut self) {
         unsafe { ffi::serialize.closeEXIStream.unwrap()(&mut *self.stream) };
     }
 }
 
-impl Writer {
-    pub fn new(header: Header, schema: Option<Schema>) -> Result<Self, EXIPError> {
+impl<'a> Writer<'a> {
+    pub fn new(header: Header, schema: Option<Schema>) -> Result<Self, Error> {
+        Self::init(header, schema.map(SchemaRef::Owned))
+    }
+
+    /// Create a writer with a schema that outlives it, so it can be used for other streams
+    pub fn with_schema(header: Header, schema: &'a mut Schema) -> Result<Self, Error> {
+        Self::init(header, Some(SchemaRef::Borrowed(schema)))
+    }
+
+    fn init(header: Header, mut schema: Option<SchemaRef<'a>>) -> Result<Self, Error> {
         let uses_schema = schema.is_some();
+        let fragment = header.options().is_some_and(|opts| opts.is_fragment());
         let mut stream: MaybeUninit<ffi::EXIStream> = MaybeUninit::uninit();
         unsafe { (ffi::serialize.initHeader).unwrap()(stream.as_mut_ptr()) };
         let ptr = stream.as_mut_ptr();
@@ -45,50 +78,168 @@ impl Writer {
             ffi::initStream(
                 &mut stream as *mut _,
                 buf,
-                schema.map_or(std::ptr::null_mut(), |mut s| s.inner.as_mut()),
+                schema.as_mut().map_or(std::ptr::null_mut(), |s| s.as_ptr()),
             )
         };
-        if ec != 0 {
-            return Err(ec.into());
-        }
+        check(ec, Operation::InitStream)?;
         let mut out = Self {
             stream: Box::new(stream),
             _buf: heap_buf,
+            _schema: schema,
             uses_schema,
             // Doesn't get read before it's written to by EXIP
             cur_tc: Box::new(0),
+            events: 0,
+            path: String::new(),
+            document: DocumentState::NotStarted,
+            fragment,
+            has_root: false,
+            has_content: false,
         };
         let ec = unsafe { ffi::serialize.exiHeader.unwrap()(out.stream.as_mut()) };
-        if ec != 0 {
-            return Err(ec.into());
-        }
+        check(ec, Operation::ExiHeader)?;
         Ok(out)
     }
 
-    pub fn add(&mut self, event: Event) -> Result<(), EXIPError> {
+    pub fn add(&mut self, event: Event) -> Result<(), Error> {
+        self.tracked(event, Self::dispatch)
+    }
+
+    /// Like `add`, but string values are taken as lexical forms and converted to the type the
+    /// schema expects. QName prefixes are looked up in `namespaces`, as (prefix, namespace) pairs
+    /// with the innermost declarations last.
+    #[cfg_attr(not(any(feature = "xml", feature = "serde")), allow(dead_code))]
+    pub(crate) fn add_lexical(
+        &mut self,
+        event: Event,
+        namespaces: &[(String, String)],
+    ) -> Result<(), Error> {
+        self.tracked(event, |w, event| match event {
+            Event::Attribute(Attribute {
+                key,
+                value: Value::String(text),
+            }) => {
+                w.attribute_key(key)?;
+                let type_id = w.stream.context.attrTypeId;
+                w.lexical(text, Some(type_id), namespaces)
+            }
+            Event::Value(Value::String(text)) => {
+                w.lexical(text, w.content_type(), namespaces)?;
+                w.has_content = true;
+                Ok(())
+            }
+            other => w.dispatch(other),
+        })
+    }
+
+    /// The options this stream is being written with
+    #[cfg_attr(not(feature = "xml"), allow(dead_code))]
+    pub(crate) fn options(&self) -> Options<'static> {
+        Options::from_ffi(&self.stream.header.opts)
+    }
+
+    /// Validate `event` and write it with `f`, adding context to any error
+    fn tracked<'e>(
+        &mut self,
+        event: Event<'e>,
+        f: impl FnOnce(&mut Self, Event<'e>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let attribute = match &event {
+            Event::Attribute(attr) => Some(attr.key.local_name),
+            _ => None,
+        };
+        let res = match self.validate(&event) {
+            Err(e) => Err(e.into()),
+            Ok(()) => f(self, event),
+        };
+        let bits = self.stream.context.bufferIndx * 8 + self.stream.context.bitPointer as usize;
+        let res = res.map_err(|e| {
+            // The open elements only change when an event is written, so are as they were
+            let mut path = match self.path.is_empty() {
+                true => "/".to_string(),
+                false => self.path.clone(),
+            };
+            if let Some(name) = attribute {
+                push_attribute(&mut path, name, !self.path.is_empty());
+            }
+            e.at_event(self.events).at_path(path).at_bit(bits)
+        });
+        self.events += 1;
+        res
+    }
+
+    pub fn get(&self) -> &[u8] {
+        &self._buf[..self.stream.buffer.bufContent]
+    }
+
+    /// Check `event` can follow the events written so far
+    fn validate(&self, event: &Event) -> Result<(), SequenceError> {
+        let name = match event {
+            Event::StartDocument => "StartDocument",
+            Event::EndDocument => "EndDocument",
+            Event::StartElement(_) => "StartElement",
+            Event::EndElement => "EndElement",
+            Event::TypeAttribute(_) => "TypeAttribute",
+            Event::Attribute(_) => "Attribute",
+            Event::NamespaceDeclaration(_) => "NamespaceDeclaration",
+            Event::Value(_) => "Value",
+        };
+        let in_element = !self.path.is_empty();
+        match (self.document, event) {
+            (DocumentState::NotStarted, Event::StartDocument) => Ok(()),
+            (DocumentState::NotStarted, _) => Err(SequenceError::BeforeStartDocument(name)),
+            (DocumentState::Ended, _) => Err(SequenceError::AfterEndDocument(name)),
+            (_, Event::StartDocument) => Err(SequenceError::DuplicateStartDocument),
+            (_, Event::EndDocument) if in_element => Err(SequenceError::UnclosedElements(
+                self.path.matches('/').count(),
+            )),
+            (_, Event::EndDocument) if !self.has_root && !self.fragment => {
+                Err(SequenceError::NoRootElement)
+            }
+            (_, Event::StartElement(_)) if !in_element && self.has_root && !self.fragment => {
+                Err(SequenceError::MultipleRootElements)
+            }
+            (_, Event::EndElement) if !in_element => Err(SequenceError::UnbalancedEndElement),
+            (
+                _,
+                Event::Attribute(_)
+                | Event::TypeAttribute(_)
+                | Event::NamespaceDeclaration(_)
+                | Event::Value(_),
+            ) if !in_element => Err(SequenceError::OutsideElement(name)),
+            (_, Event::Attribute(_) | Event::TypeAttribute(_) | Event::NamespaceDeclaration(_))
+                if self.has_content =>
+            {
+                Err(SequenceError::AfterContent(name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn dispatch(&mut self, event: Event) -> Result<(), Error> {
         match event {
             Event::StartDocument => self.start_document(),
             Event::EndDocument => self.end_document(),
             Event::StartElement(name) => self.start_element(name),
             Event::EndElement => self.end_element(),
             Event::Attribute(attr) => self.attribute(attr),
-            Event::Value(val) => self.value(&val),
+            Event::Value(val) => {
+                self.value(&val)?;
+                self.has_content = true;
+                Ok(())
+            }
             Event::NamespaceDeclaration(ns) => self.namespace(ns),
             Event::TypeAttribute(name) => self.type_value(name),
         }
     }
 
-    pub fn get(&self) -> &[u8] {
-        &self._buf[..self.stream.buffer.bufContent]
-    }
-
-    fn value(&mut self, value: &Value) -> Result<(), EXIPError> {
+    fn value(&mut self, value: &Value) -> Result<(), Error> {
         if self.uses_schema {
             match value {
                 Value::Integer(int) => self.integer(*int),
                 Value::Boolean(bool) => self.boolean(*bool),
                 Value::String(str) => self.characters(str),
-                Value::Float(float) => self.float(*float),
+                Value::Float(float) | Value::Decimal(float) => self.float(*float),
                 Value::Binary(binary) => self.binary(binary),
                 Value::Timestamp(ts) => self.timestamp(ts),
                 Value::List(list) => self.list(list),
@@ -102,50 +253,51 @@ impl Writer {
         }
     }
 
-    fn start_document(&mut self) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.startDocument.unwrap()(self.stream.as_mut()) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn start_document(&mut self) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.startDocument.unwrap()(self.stream.as_mut()) };
+        check(ec, Operation::StartDocument)?;
+        self.document = DocumentState::Started;
+        Ok(())
     }
 
-    fn end_document(&mut self) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.endDocument.unwrap()(self.stream.as_mut()) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn end_document(&mut self) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.endDocument.unwrap()(self.stream.as_mut()) };
+        check(ec, Operation::EndDocument)?;
+        self.document = DocumentState::Ended;
+        Ok(())
     }
 
-    fn start_element(&mut self, name: Name) -> Result<(), EXIPError> {
+    fn start_element(&mut self, name: Name) -> Result<(), Error> {
         let qname = to_qname!(name);
-        unsafe {
-            match ffi::serialize.startElement.unwrap()(
-                self.stream.as_mut(),
-                qname,
-                self.cur_tc.as_mut(),
-            ) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+        let ec = unsafe {
+            ffi::serialize.startElement.unwrap()(self.stream.as_mut(), qname, self.cur_tc.as_mut())
+        };
+        check(ec, Operation::StartElement)?;
+        self.path.push('/');
+        self.path.push_str(name.local_name);
+        self.has_root = true;
+        self.has_content = false;
+        Ok(())
     }
 
-    fn end_element(&mut self) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.endElement.unwrap()(self.stream.as_mut()) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn end_element(&mut self) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.endElement.unwrap()(self.stream.as_mut()) };
+        check(ec, Operation::EndElement)?;
+        let parent = self.path.rfind('/').unwrap_or_default();
+        self.path.truncate(parent);
+        // The parent now has this element as content
+        self.has_content = true;
+        Ok(())
+    }
+
+    fn attribute(&mut self, attr: Attribute) -> Result<(), Error> {
+        self.attribute_key(attr.key)?;
+        self.value(&attr.value)
     }
 
-    fn attribute(&mut self, attr: Attribute) -> Result<(), EXIPError> {
+    fn attribute_key(&mut self, key: Name) -> Result<(), Error> {
         // Inlined to keep the StringTypes in scope
-        let qname = to_qname!(attr.key);
+        let qname = to_qname!(key);
         let ec = unsafe {
             ffi::serialize.attribute.unwrap()(
                 self.stream.as_mut(),
@@ -154,92 +306,64 @@ impl Writer {
                 self.cur_tc.as_mut(),
             )
         };
-        match ec {
-            0 => Ok::<(), EXIPError>(()),
-            e => Err(e.into()),
-        }?;
-        self.value(&attr.value)
+        check(ec, Operation::Attribute)
     }
 
-    fn integer(&mut self, int: i64) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.intData.unwrap()(self.stream.as_mut(), int) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn integer(&mut self, int: i64) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.intData.unwrap()(self.stream.as_mut(), int) };
+        check(ec, Operation::IntData)
     }
 
-    fn boolean(&mut self, bool: bool) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.booleanData.unwrap()(self.stream.as_mut(), bool as u32) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn boolean(&mut self, bool: bool) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.booleanData.unwrap()(self.stream.as_mut(), bool as u32) };
+        check(ec, Operation::BooleanData)
     }
 
-    fn characters(&mut self, characters: &str) -> Result<(), EXIPError> {
-        unsafe {
-            let chval = to_stringtype(characters);
-            match ffi::serialize.stringData.unwrap()(self.stream.as_mut(), chval) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn characters(&mut self, characters: &str) -> Result<(), Error> {
+        let chval = to_stringtype(characters);
+        let ec = unsafe { ffi::serialize.stringData.unwrap()(self.stream.as_mut(), chval) };
+        check(ec, Operation::StringData)
     }
 
-    fn float(&mut self, float: f64) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.floatData.unwrap()(self.stream.as_mut(), float.into()) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+    fn float(&mut self, float: f64) -> Result<(), Error> {
+        let ec = unsafe { ffi::serialize.floatData.unwrap()(self.stream.as_mut(), float.into()) };
+        check(ec, Operation::FloatData)
     }
 
-    fn binary(&mut self, binary: &[u8]) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.binaryData.unwrap()(
+    fn binary(&mut self, binary: &[u8]) -> Result<(), Error> {
+        let ec = unsafe {
+            ffi::serialize.binaryData.unwrap()(
                 self.stream.as_mut(),
                 binary.as_ptr() as *const _,
                 binary.len(),
-            ) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+            )
+        };
+        check(ec, Operation::BinaryData)
     }
 
-    fn timestamp(&mut self, ts: &chrono::NaiveDateTime) -> Result<(), EXIPError> {
+    fn timestamp(&mut self, ts: &chrono::NaiveDateTime) -> Result<(), Error> {
         let dt: ffi::EXIPDateTime = ts.into();
-        unsafe {
-            match ffi::serialize.dateTimeData.unwrap()(self.stream.as_mut(), dt) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+        let ec = unsafe { ffi::serialize.dateTimeData.unwrap()(self.stream.as_mut(), dt) };
+        check(ec, Operation::DateTimeData)
     }
 
-    fn list(&mut self, list: &[Value]) -> Result<(), EXIPError> {
-        unsafe {
-            match ffi::serialize.listData.unwrap()(
-                self.stream.as_mut(),
-                list.len()
-                    .try_into()
-                    .map_err(|_| EXIPError::InvalidEXIInput)?,
-            ) {
-                0 => Ok::<(), EXIPError>(()),
-                e => Err(e.into()),
-            }
-        }?;
+    fn list(&mut self, list: &[Value]) -> Result<(), Error> {
+        self.list_length(list.len())?;
         for each in list {
             self.value(each)?;
         }
         Ok(())
     }
 
-    fn type_value(&mut self, name: Name) -> Result<(), EXIPError> {
+    fn list_length(&mut self, len: usize) -> Result<(), Error> {
+        let len = len
+            .try_into()
+            .map_err(|_| Error::from(EXIPError::InvalidEXIInput).during(Operation::ListData))?;
+        let ec = unsafe { ffi::serialize.listData.unwrap()(self.stream.as_mut(), len) };
+        check(ec, Operation::ListData)
+    }
+
+    fn type_value(&mut self, name: Name) -> Result<(), Error> {
         let typename = Name {
             local_name: "type",
             namespace: Some("http://www.w3.org/2001/XMLSchema-instance"),
@@ -254,47 +378,231 @@ impl Writer {
                 self.cur_tc.as_mut(),
             )
         };
-        match ec {
-            0 => Ok::<(), EXIPError>(()),
-            e => Err(e.into()),
-        }?;
+        check(ec, Operation::Attribute)?;
         let qname = to_qname!(name);
-        unsafe {
-            match ffi::serialize.qnameData.unwrap()(self.stream.as_mut(), qname) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+        let ec = unsafe { ffi::serialize.qnameData.unwrap()(self.stream.as_mut(), qname) };
+        check(ec, Operation::QNameData)
     }
 
-    fn namespace(&mut self, dec: NamespaceDeclaration) -> Result<(), EXIPError> {
+    fn namespace(&mut self, dec: NamespaceDeclaration) -> Result<(), Error> {
         let ns = to_stringtype(dec.namespace);
         let prefix = to_stringtype(dec.prefix);
-        unsafe {
-            match ffi::serialize.namespaceDeclaration.unwrap()(
+        let ec = unsafe {
+            ffi::serialize.namespaceDeclaration.unwrap()(
                 self.stream.as_mut(),
                 ns,
                 prefix,
                 dec.is_local_element as u32,
-            ) {
-                0 => Ok(()),
-                e => Err(e.into()),
-            }
-        }
+            )
+        };
+        check(ec, Operation::NamespaceDeclaration)
     }
 
-    fn qname(&mut self, name: &Name) -> Result<(), EXIPError> {
+    fn qname(&mut self, name: &Name) -> Result<(), Error> {
         let qname = to_qname!(name);
-        unsafe {
-            match ffi::serialize.qnameData.unwrap()(self.stream.as_mut(), qname) {
-                0 => Ok(()),
-                e => Err(e.into()),
+        let ec = unsafe { ffi::serialize.qnameData.unwrap()(self.stream.as_mut(), qname) };
+        check(ec, Operation::QNameData)
+    }
+
+    /// Simple type of the character content the current grammar rule allows, if any
+    fn content_type(&self) -> Option<usize> {
+        let grammar = unsafe { self.stream.gStack.as_ref()?.grammar.as_ref()? };
+        let rule = unsafe { table(grammar.rule, grammar.count as usize) }
+            .get(self.stream.context.currNonTermID as usize)?;
+        unsafe { table(rule.production, rule.pCount) }
+            .iter()
+            .find(|prod| prod.content >> 24 == ffi::EventType_EVENT_CH)
+            .map(|prod| prod.typeId)
+    }
+
+    /// Write `text` as a value of the simple type `type_id`
+    fn lexical(
+        &mut self,
+        text: &str,
+        type_id: Option<usize>,
+        namespaces: &[(String, String)],
+    ) -> Result<(), Error> {
+        let schema = unsafe { self.stream.schema.as_ref() };
+        let simple = match (schema, type_id) {
+            _ if self.options().preserves_lexical_values() => None,
+            (Some(schema), Some(id)) => simple_type(schema, id).copied(),
+            _ => None,
+        };
+        let Some(simple) = simple else {
+            return self.characters(text);
+        };
+        let class = type_class(&simple);
+        let invalid = || Error::new(ErrorKind::InvalidLexical(text.to_string(), class));
+        let trimmed = text.trim();
+        match class {
+            TypeClass::Integer | TypeClass::SmallInteger | TypeClass::NonNegativeInteger => {
+                self.integer(trimmed.parse().map_err(|_| invalid())?)
+            }
+            TypeClass::Boolean => match trimmed {
+                "true" | "1" => self.boolean(true),
+                "false" | "0" => self.boolean(false),
+                _ => Err(invalid()),
+            },
+            TypeClass::Float | TypeClass::Decimal => {
+                self.float(trimmed.parse().map_err(|_| invalid())?)
+            }
+            TypeClass::DateTime
+            | TypeClass::Date
+            | TypeClass::Time
+            | TypeClass::Year
+            | TypeClass::Month => {
+                self.timestamp(&parse_timestamp(trimmed, class).ok_or_else(invalid)?)
+            }
+            TypeClass::Binary => {
+                let compact: String = trimmed.split_whitespace().collect();
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(compact)
+                    .map_err(|_| invalid())?;
+                self.binary(&bytes)
             }
+            TypeClass::List => {
+                let items: Vec<&str> = trimmed.split_whitespace().collect();
+                self.list_length(items.len())?;
+                // EXIP keeps the item type in the length facet of list types
+                for item in items {
+                    self.lexical(item, Some(simple.length), namespaces)?;
+                }
+                Ok(())
+            }
+            TypeClass::QName => {
+                let (prefix, local_name) = trimmed.split_once(':').unwrap_or(("", trimmed));
+                let namespace = namespaces
+                    .iter()
+                    .rev()
+                    .find(|(p, _)| p == prefix)
+                    .map(|(_, ns)| ns.as_str());
+                if namespace.is_none() && !prefix.is_empty() {
+                    return Err(invalid());
+                }
+                self.qname(&Name {
+                    local_name,
+                    namespace: namespace.filter(|ns| !ns.is_empty()),
+                    prefix: Some(prefix).filter(|p| !p.is_empty()),
+                })
+            }
+            TypeClass::String | TypeClass::Untyped | TypeClass::None => self.characters(text),
+        }
+    }
+}
+
+/// Parse an XML Schema date or time, ignoring any timezone
+fn parse_timestamp(text: &str, class: TypeClass) -> Option<NaiveDateTime> {
+    let bytes = text.as_bytes();
+    let text = match bytes.len().checked_sub(6).map(|i| (bytes[i], bytes[i + 3])) {
+        _ if text.ends_with('Z') => &text[..text.len() - 1],
+        Some((b'+' | b'-', b':')) => &text[..text.len() - 6],
+        _ => text,
+    };
+    let midnight = NaiveTime::MIN;
+    match class {
+        TypeClass::DateTime => NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok(),
+        TypeClass::Date => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.and_time(midnight)),
+        TypeClass::Time => NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+            .ok()
+            .map(|time| NaiveDate::default().and_time(time)),
+        // gYear and gYearMonth
+        TypeClass::Year => {
+            let (year, month) = text.rsplit_once('-').unwrap_or((text, "1"));
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+                .map(|date| date.and_time(midnight))
+        }
+        // gMonth, gMonthDay and gDay
+        TypeClass::Month => {
+            let (month, day) = match text.strip_prefix("---") {
+                Some(day) => ("1", day),
+                None => {
+                    let rest = text.strip_prefix("--")?;
+                    rest.split_once('-').unwrap_or((rest, "1"))
+                }
+            };
+            NaiveDate::from_ymd_opt(1970, month.parse().ok()?, day.parse().ok()?)
+                .map(|date| date.and_time(midnight))
         }
+        _ => None,
+    }
+}
+
+fn push_attribute(path: &mut String, local_name: &str, in_element: bool) {
+    if in_element {
+        path.push('/');
+    }
+    path.push('@');
+    path.push_str(local_name);
+}
+
+impl<'a> Writer<'a> {
+    /// Write a whole document, adding its content in `f`
+    pub fn document(
+        &mut self,
+        f: impl FnOnce(&mut Scope) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.add(Event::StartDocument)?;
+        f(&mut Scope { writer: self })?;
+        self.add(Event::EndDocument)
     }
+
+    /// Write an element, adding its attributes and content in `f`
+    pub fn element<'n>(
+        &mut self,
+        name: impl Into<Name<'n>>,
+        f: impl FnOnce(&mut Scope) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        Scope { writer: self }.element(name, f)
+    }
+}
+
+/// The inside of a document or element opened by `Writer::document` or `Writer::element`.
+/// The matching end event is written once the scope's closure returns.
+pub struct Scope<'w, 'a> {
+    writer: &'w mut Writer<'a>,
 }
 
-impl Default for Writer {
+impl<'w, 'a> Scope<'w, 'a> {
+    pub fn element<'n>(
+        &mut self,
+        name: impl Into<Name<'n>>,
+        f: impl FnOnce(&mut Scope) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.writer.add(Event::StartElement(name.into()))?;
+        f(&mut Scope {
+            writer: self.writer,
+        })?;
+        self.writer.add(Event::EndElement)
+    }
+
+    pub fn attr<'n>(&mut self, key: impl Into<Name<'n>>, value: Value) -> Result<(), Error> {
+        self.writer.add(Event::Attribute(Attribute {
+            key: key.into(),
+            value,
+        }))
+    }
+
+    /// Write an `xsi:type` attribute
+    pub fn type_attr<'n>(&mut self, name: impl Into<Name<'n>>) -> Result<(), Error> {
+        self.writer.add(Event::TypeAttribute(name.into()))
+    }
+
+    pub fn namespace(&mut self, dec: NamespaceDeclaration) -> Result<(), Error> {
+        self.writer.add(Event::NamespaceDeclaration(dec))
+    }
+
+    pub fn value(&mut self, value: Value) -> Result<(), Error> {
+        self.writer.add(Event::Value(value))
+    }
+
+    pub fn text(&mut self, text: &str) -> Result<(), Error> {
+        self.value(Value::String(text))
+    }
+}
+
+impl<'a> Default for Writer<'a> {
     fn default() -> Self {
         // Default configuration should never fail
         Self::new(Header::default(), None).unwrap()
@@ -341,20 +649,13 @@ fn simple_schemaless_write() {
 #[test]
 fn full_write() {
     use crate::config::Options;
+    use crate::testing::{example_schema, FULL_DOCUMENT};
     use bytes::Bytes;
     use chrono::NaiveDateTime;
 
     let options = Options::default().strict(true);
     let header = Header::with_options(options).has_cookie(true);
-    let schema = Schema::new(
-        &[
-            "./examples/exipe-test-xsd.exi",
-            "./examples/exipe-test-types-xsd.exi",
-            "./examples/exipe-test-nested-xsd.exi",
-        ],
-        None,
-    )
-    .unwrap();
+    let schema = example_schema(None);
     let mut builder = Writer::new(header, Some(schema)).unwrap();
     builder.add(Event::StartDocument).unwrap();
     builder
@@ -517,27 +818,217 @@ fn full_write() {
     builder.add(Event::EndElement).unwrap();
     builder.add(Event::EndDocument).unwrap();
     // From EXIP output
+    assert_eq!(builder.get(), FULL_DOCUMENT);
+}
+
+#[test]
+fn error_context() {
+    use crate::config::Options;
+    use crate::testing::example_schema;
+
+    let options = Options::default().strict(true);
+    let header = Header::with_options(options).has_cookie(true);
+    let schema = example_schema(None);
+    let mut builder = Writer::new(header, Some(schema)).unwrap();
+    builder.add(Event::StartDocument).unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "MultipleXSDsTest",
+            namespace: Some("http://www.ltu.se/EISLAB/schema-test"),
+            prefix: None,
+        }))
+        .unwrap();
+    builder
+        .add(Event::StartElement(Name {
+            local_name: "EXIPEncoder",
+            namespace: Some("http://www.ltu.se/EISLAB/schema-test"),
+            prefix: None,
+        }))
+        .unwrap();
+    // testByte is typed as a byte, which a strict stream can't hold a string in
+    let err = builder
+        .add(Event::Attribute(Attribute {
+            key: Name {
+                local_name: "testByte",
+                namespace: None,
+                prefix: None,
+            },
+            value: Value::String("fifty-five"),
+        }))
+        .unwrap_err();
+    assert_eq!(err.event_index(), Some(3));
+    assert_eq!(err.path(), Some("/MultipleXSDsTest/EXIPEncoder/@testByte"));
+    assert!(err.operation().is_some());
+    assert!(err.bit_offset().is_some());
+}
+
+#[test]
+fn invalid_sequences() {
+    use crate::error::ErrorKind;
+
+    let root = Name {
+        local_name: "root",
+        namespace: None,
+        prefix: None,
+    };
+    let attr = Attribute {
+        key: Name {
+            local_name: "id",
+            namespace: None,
+            prefix: None,
+        },
+        value: Value::Integer(1),
+    };
+    let kind = |e: Error| e.kind().clone();
+
+    let mut builder = Writer::default();
+    assert_eq!(
+        builder.add(Event::StartElement(root.clone())).map_err(kind),
+        Err(ErrorKind::Sequence(SequenceError::BeforeStartDocument(
+            "StartElement"
+        )))
+    );
+    builder.add(Event::StartDocument).unwrap();
+    assert_eq!(
+        builder.add(Event::EndElement).map_err(kind),
+        Err(ErrorKind::Sequence(SequenceError::UnbalancedEndElement))
+    );
+    builder.add(Event::StartElement(root.clone())).unwrap();
+    builder.add(Event::Value(Value::String("text"))).unwrap();
+    let err = builder.add(Event::Attribute(attr)).unwrap_err();
+    assert_eq!(err.path(), Some("/root/@id"));
+    assert_eq!(
+        kind(err),
+        ErrorKind::Sequence(SequenceError::AfterContent("Attribute"))
+    );
+    assert_eq!(
+        builder.add(Event::EndDocument).map_err(kind),
+        Err(ErrorKind::Sequence(SequenceError::UnclosedElements(1)))
+    );
+    builder.add(Event::EndElement).unwrap();
+    assert_eq!(
+        builder.add(Event::StartElement(root)).map_err(kind),
+        Err(ErrorKind::Sequence(SequenceError::MultipleRootElements))
+    );
+    builder.add(Event::EndDocument).unwrap();
+    assert_eq!(
+        builder.add(Event::EndDocument).map_err(kind),
+        Err(ErrorKind::Sequence(SequenceError::AfterEndDocument(
+            "EndDocument"
+        )))
+    );
+}
+
+#[test]
+fn scoped_write() {
+    use crate::config::Options;
+
+    let options = Options::default().strict(true);
+    let header = Header::with_options(options).has_cookie(true);
+    let mut builder = Writer::new(header, None).unwrap();
+    builder
+        .document(|d| {
+            d.element(
+                Name {
+                    local_name: "MultipleXSDsTest",
+                    namespace: Some("http://www.ltu.se/EISLAB/schema-test"),
+                    prefix: None,
+                },
+                |e| {
+                    e.text("This is an example of serializing EXI streams using EXIP low level API")
+                },
+            )
+        })
+        .unwrap();
     assert_eq!(
         builder.get(),
         [
-            0x24, 0x45, 0x58, 0x49, 0xA0, 0x49, 0x6E, 0x05, 0x30, 0x2E, 0x32, 0x48, 0x54, 0x68,
-            0x69, 0x73, 0x20, 0x69, 0x73, 0x20, 0x61, 0x6E, 0x20, 0x65, 0x78, 0x61, 0x6D, 0x70,
-            0x6C, 0x65, 0x20, 0x6F, 0x66, 0x20, 0x73, 0x65, 0x72, 0x69, 0x61, 0x6C, 0x69, 0x7A,
-            0x69, 0x6E, 0x67, 0x20, 0x45, 0x58, 0x49, 0x20, 0x73, 0x74, 0x72, 0x65, 0x61, 0x6D,
-            0x73, 0x20, 0x75, 0x73, 0x69, 0x6E, 0x67, 0x20, 0x45, 0x58, 0x49, 0x50, 0x20, 0x6C,
-            0x6F, 0x77, 0x20, 0x6C, 0x65, 0x76, 0x65, 0x6C, 0x20, 0x41, 0x50, 0x49, 0x21, 0x2A,
-            0x34, 0x34, 0xB9, 0x90, 0x34, 0xB9, 0x90, 0x30, 0x90, 0x3A, 0x32, 0xB9, 0xBA, 0x10,
-            0x37, 0xB3, 0x10, 0x38, 0x39, 0x37, 0xB1, 0xB2, 0xB9, 0xB9, 0xB4, 0xB7, 0x33, 0x90,
-            0x2C, 0x26, 0xA6, 0x10, 0x39, 0xB1, 0xB4, 0x32, 0xB6, 0xB2, 0xB9, 0x90, 0x3B, 0xB4,
-            0xBA, 0x34, 0x10, 0x36, 0xBA, 0xB6, 0x3A, 0x34, 0xB8, 0x36, 0x32, 0x90, 0x2C, 0x29,
-            0xA2, 0x10, 0x33, 0x34, 0xB6, 0x32, 0xB9, 0x89, 0xD5, 0x99, 0x5C, 0x9A, 0x59, 0x9E,
-            0x48, 0x1D, 0x1A, 0x18, 0x5D, 0x08, 0x1D, 0x1A, 0x19, 0x48, 0x1A, 0x5B, 0x5C, 0x1B,
-            0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x18, 0x5D, 0x1A, 0x5B, 0xDB, 0x88, 0x1D, 0xDB, 0xDC,
-            0x9A, 0xDC, 0xC8, 0x4A, 0xD4, 0xDA, 0x5B, 0x5C, 0x1B, 0x19, 0x48, 0x1D, 0x19, 0x5C,
-            0xDD, 0x08, 0x19, 0x5B, 0x19, 0x5B, 0x59, 0x5B, 0x9D, 0x08, 0x1D, 0xDA, 0x5D, 0x1A,
-            0x08, 0x1C, 0xDA, 0x5B, 0x99, 0xDB, 0x19, 0x48, 0x18, 0x5D, 0x1D, 0x1C, 0x9A, 0x58,
-            0x9D, 0x5D, 0x19, 0x47, 0x48, 0x3A, 0x8B, 0x06, 0x3F, 0xDB, 0x0E, 0xF9, 0x0A, 0x03,
-            0x90, 0x14, 0x04, 0xDA, 0x5F, 0x4A, 0x41, 0xE4, 0xC3, 0x39, 0xDC, 0x1E, 0xC0,
+            36, 69, 88, 73, 160, 65, 35, 67, 163, 163, 129, 209, 121, 123, 187, 187, 185, 115, 99,
+            163, 169, 115, 155, 41, 122, 42, 74, 154, 98, 10, 17, 123, 155, 27, 67, 43, 107, 9,
+            107, 163, 43, 155, 160, 138, 107, 171, 99, 163, 75, 131, 99, 42, 194, 154, 35, 154,
+            163, 43, 155, 166, 144, 168, 208, 210, 230, 64, 210, 230, 64, 194, 220, 64, 202, 240,
+            194, 218, 224, 216, 202, 64, 222, 204, 64, 230, 202, 228, 210, 194, 216, 210, 244, 210,
+            220, 206, 64, 138, 176, 146, 64, 230, 232, 228, 202, 194, 218, 230, 64, 234, 230, 210,
+            220, 206, 64, 138, 176, 146, 160, 64, 216, 222, 238, 64, 216, 202, 236, 202, 216, 64,
+            130, 160, 146
         ]
-    );
+    )
+}
+
+#[test]
+fn scoped_nesting() {
+    let mut scoped = Writer::default();
+    scoped
+        .document(|d| {
+            d.element("root", |e| {
+                e.attr("id", Value::Integer(7))?;
+                e.element("child", |c| c.text("one"))?;
+                e.element("child", |_| Ok(()))
+            })
+        })
+        .unwrap();
+
+    let mut flat = Writer::default();
+    let name = |local_name| Name {
+        local_name,
+        namespace: None,
+        prefix: None,
+    };
+    flat.add(Event::StartDocument).unwrap();
+    flat.add(Event::StartElement(name("root"))).unwrap();
+    flat.add(Event::Attribute(Attribute {
+        key: name("id"),
+        value: Value::Integer(7),
+    }))
+    .unwrap();
+    flat.add(Event::StartElement(name("child"))).unwrap();
+    flat.add(Event::Value(Value::String("one"))).unwrap();
+    flat.add(Event::EndElement).unwrap();
+    flat.add(Event::StartElement(name("child"))).unwrap();
+    flat.add(Event::EndElement).unwrap();
+    flat.add(Event::EndElement).unwrap();
+    flat.add(Event::EndDocument).unwrap();
+    assert_eq!(scoped.get(), flat.get());
+}
+
+#[test]
+fn schemaless_values() {
+    use crate::{
+        data::{OwnedEvent, OwnedValue},
+        Reader,
+    };
+
+    let timestamp = chrono::NaiveDate::from_ymd_opt(2012, 7, 31)
+        .unwrap()
+        .and_hms_opt(13, 33, 55)
+        .unwrap();
+    let mut writer = Writer::default();
+    writer
+        .document(|d| {
+            d.element("root", |e| {
+                e.element("float", |e| e.value(Value::Float(3.0)))?;
+                e.element("timestamp", |e| e.value(Value::Timestamp(&timestamp)))?;
+                e.element("list", |e| {
+                    e.value(Value::List(vec![Value::Integer(1), Value::Integer(2)]))
+                })?;
+                e.element("qname", |e| {
+                    e.value(Value::QName(Name {
+                        local_name: "local",
+                        namespace: Some("urn:a"),
+                        prefix: Some("a"),
+                    }))
+                })
+            })
+        })
+        .unwrap();
+
+    // Written as their `Display` forms
+    let reader = Reader::new(writer.get().to_vec(), None, None).unwrap();
+    let text: Vec<_> = reader
+        .filter_map(|event| match event.unwrap().into() {
+            OwnedEvent::Value(OwnedValue::String(text)) => Some(text),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(text, ["3.0E0", "2012-07-31T13:33:55", "1 2", "a:local"]);
 }
@@ -0,0 +1,690 @@
+//! Conversion between XML text and EXI.
+//!
+//! When encoding, comments, processing instructions and DTDs are dropped, as `Writer` can't
+//! encode them, unless the header preserves them, which is an error. Whitespace-only text
+//! between child elements is dropped too. Namespace declarations are only written when the
+//! header preserves prefixes.
+//!
+//! When decoding, prefixes come from the stream's namespace declarations if it has them,
+//! and are generated otherwise. Values are written in the canonical lexical forms of `Value`'s
+//! `Display`, declaring the namespaces of QNames.
+
+use std::io::{BufRead, Write};
+
+use bytes::Bytes;
+use quick_xml::{
+    events::{BytesStart, Event as XmlEvent},
+    name::{PrefixDeclaration, QName, ResolveResult},
+    NsReader,
+};
+
+use crate::{
+    config::{Header, Schema},
+    data::{Attribute, Event, Name, NamespaceDeclaration, Value},
+    Error, Reader, Writer,
+};
+
+const XSI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum XmlError {
+    #[error("malformed XML at byte {position}: {source}")]
+    Syntax {
+        position: u64,
+        source: quick_xml::Error,
+    },
+    #[error("undeclared namespace prefix `{0}`")]
+    UnboundPrefix(String),
+    #[error("the header preserves {0}, which can't be encoded")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Exi(#[from] Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Encode an XML document
+pub fn encode<R: BufRead>(
+    xml: R,
+    header: Header,
+    schema: Option<Schema>,
+) -> Result<Bytes, XmlError> {
+    let mut writer = Writer::new(header, schema)?;
+    write(xml, &mut writer)?;
+    Ok(Bytes::copy_from_slice(writer.get()))
+}
+
+/// Add an XML document to `writer`, from StartDocument to EndDocument.
+///
+/// Text and attribute values are converted from their lexical form to the
+/// value type the writer's schema expects.
+pub fn write<R: BufRead>(xml: R, writer: &mut Writer) -> Result<(), XmlError> {
+    let mut reader = NsReader::from_reader(xml);
+    let options = writer.options();
+    let prefixes = options.preserves_prefixes();
+    let comments = options.preserves_comments();
+    let pis = options.preserves_processing_instructions();
+    let dtd = options.preserves_dt_and_er();
+    let mut buf = vec![];
+    let mut text = String::new();
+    // Whether the last markup closed an element, so whitespace before an end tag is indentation
+    let mut after_child = false;
+    writer.add(Event::StartDocument)?;
+    loop {
+        buf.clear();
+        let (namespace, event) = match reader.read_resolved_event_into(&mut buf) {
+            Ok((ns, event)) => (resolved(ns)?, event),
+            Err(e) => return Err(at(&reader)(e)),
+        };
+        match event {
+            XmlEvent::Start(start) => {
+                flush(&reader, writer, &mut text, false)?;
+                start_element(&reader, writer, namespace, &start, prefixes)?;
+                after_child = false;
+            }
+            XmlEvent::Empty(start) => {
+                flush(&reader, writer, &mut text, false)?;
+                start_element(&reader, writer, namespace, &start, prefixes)?;
+                writer.add(Event::EndElement)?;
+                after_child = true;
+            }
+            XmlEvent::End(_) => {
+                flush(&reader, writer, &mut text, !after_child)?;
+                writer.add(Event::EndElement)?;
+                after_child = true;
+            }
+            XmlEvent::Text(t) => text.push_str(&t.unescape().map_err(at(&reader))?),
+            XmlEvent::CData(t) => text.push_str(&t.decode().map_err(at(&reader))?),
+            XmlEvent::Comment(_) if comments => return Err(XmlError::Unsupported("comments")),
+            XmlEvent::PI(_) if pis => return Err(XmlError::Unsupported("processing instructions")),
+            XmlEvent::DocType(_) if dtd => return Err(XmlError::Unsupported("DTDs")),
+            XmlEvent::Eof => break,
+            // The XML declaration, and comments, processing instructions and DTDs not preserved
+            _ => {}
+        }
+    }
+    writer.add(Event::EndDocument)?;
+    Ok(())
+}
+
+fn start_element<R>(
+    reader: &NsReader<R>,
+    writer: &mut Writer,
+    namespace: Option<String>,
+    start: &BytesStart,
+    prefixes: bool,
+) -> Result<(), XmlError> {
+    let decoder = reader.decoder();
+    let local_name = decoder
+        .decode(start.local_name().into_inner())
+        .map_err(at(reader))?;
+    let prefix = match start.name().prefix() {
+        Some(p) => Some(decoder.decode(p.into_inner()).map_err(at(reader))?),
+        None => None,
+    };
+    writer.add(Event::StartElement(Name {
+        local_name: &local_name,
+        namespace: namespace.as_deref(),
+        prefix: prefix.as_deref().filter(|_| prefixes),
+    }))?;
+
+    let mut declarations = vec![];
+    let mut type_name = None;
+    let mut attributes = vec![];
+    for attr in start.attributes() {
+        let attr = attr.map_err(at(reader))?;
+        let value = attr
+            .decode_and_unescape_value(decoder)
+            .map_err(at(reader))?
+            .into_owned();
+        match attr.key.as_namespace_binding() {
+            Some(PrefixDeclaration::Default) => declarations.push((String::new(), value)),
+            Some(PrefixDeclaration::Named(p)) => {
+                let p = decoder.decode(p).map_err(at(reader))?;
+                declarations.push((p.into_owned(), value));
+            }
+            None => {
+                let (ns, local) = reader.resolve_attribute(attr.key);
+                let ns = resolved(ns)?;
+                let local = decoder.decode(local.into_inner()).map_err(at(reader))?;
+                if ns.as_deref() == Some(XSI) && local == "type" {
+                    type_name = Some(value);
+                } else {
+                    let prefix = match attr.key.prefix() {
+                        Some(p) => Some(decoder.decode(p.into_inner()).map_err(at(reader))?),
+                        None => None,
+                    };
+                    attributes.push((ns, local.into_owned(), prefix, value));
+                }
+            }
+        }
+    }
+
+    if prefixes {
+        for (p, ns) in &declarations {
+            writer.add(Event::NamespaceDeclaration(NamespaceDeclaration {
+                namespace: ns,
+                prefix: p,
+                is_local_element: prefix.as_deref().unwrap_or_default() == p,
+            }))?;
+        }
+    }
+    if let Some(type_name) = type_name {
+        let qname = QName(type_name.trim().as_bytes());
+        let (ns, local) = reader.resolve_element(qname);
+        let ns = resolved(ns)?;
+        let local = decoder.decode(local.into_inner()).map_err(at(reader))?;
+        let type_prefix = match qname.prefix() {
+            Some(p) => Some(decoder.decode(p.into_inner()).map_err(at(reader))?),
+            None => None,
+        };
+        writer.add(Event::TypeAttribute(Name {
+            local_name: &local,
+            namespace: ns.as_deref(),
+            prefix: type_prefix.as_deref().filter(|_| prefixes),
+        }))?;
+    }
+    // Schema-informed grammars expect attributes sorted by local name, then namespace
+    attributes.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+    let scope = scope(reader)?;
+    for (ns, local, prefix, value) in &attributes {
+        let key = Name {
+            local_name: local,
+            namespace: ns.as_deref(),
+            prefix: prefix.as_deref().filter(|_| prefixes),
+        };
+        writer.add_lexical(
+            Event::Attribute(Attribute {
+                key,
+                value: Value::String(value),
+            }),
+            &scope,
+        )?;
+    }
+    Ok(())
+}
+
+/// Write any pending text, unless it's whitespace that `whitespace` doesn't keep
+fn flush<R>(
+    reader: &NsReader<R>,
+    writer: &mut Writer,
+    text: &mut String,
+    whitespace: bool,
+) -> Result<(), XmlError> {
+    if !text.is_empty() && (whitespace || !text.trim().is_empty()) {
+        writer.add_lexical(Event::Value(Value::String(text)), &scope(reader)?)?;
+    }
+    text.clear();
+    Ok(())
+}
+
+/// The namespace prefixes in scope, innermost last
+fn scope<R>(reader: &NsReader<R>) -> Result<Vec<(String, String)>, XmlError> {
+    let decoder = reader.decoder();
+    reader
+        .prefixes()
+        .map(|(prefix, ns)| {
+            let prefix = match prefix {
+                PrefixDeclaration::Default => String::new(),
+                PrefixDeclaration::Named(p) => decoder.decode(p).map_err(at(reader))?.into_owned(),
+            };
+            let ns = decoder.decode(ns.into_inner()).map_err(at(reader))?;
+            Ok((prefix, ns.into_owned()))
+        })
+        .collect()
+}
+
+fn resolved(ns: ResolveResult) -> Result<Option<String>, XmlError> {
+    match ns {
+        ResolveResult::Bound(ns) => Ok(Some(String::from_utf8_lossy(ns.into_inner()).into())),
+        ResolveResult::Unbound => Ok(None),
+        ResolveResult::Unknown(p) => Err(XmlError::UnboundPrefix(
+            String::from_utf8_lossy(&p).into_owned(),
+        )),
+    }
+}
+
+/// Convert a quick-xml error, recording where the reader got to
+fn at<R, E: Into<quick_xml::Error>>(reader: &NsReader<R>) -> impl Fn(E) -> XmlError {
+    let position = reader.buffer_position();
+    move |e| XmlError::Syntax {
+        position,
+        source: e.into(),
+    }
+}
+
+/// Decode a stream to XML text
+pub fn decode(reader: Reader) -> Result<String, XmlError> {
+    let mut out = vec![];
+    to_writer(reader, &mut out)?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Decode a stream, writing it to `out` as XML text
+pub fn to_writer(reader: Reader, mut out: impl Write) -> Result<(), XmlError> {
+    let fragment = reader.is_fragment();
+    let mut xml = XmlWriter {
+        bindings: vec![("xml".into(), XML.into())],
+        open: vec![],
+        generated: 0,
+    };
+    for event in reader {
+        match event? {
+            Event::StartDocument if !fragment => {
+                out.write_all(br#"<?xml version="1.0" encoding="UTF-8"?>"#)?
+            }
+            Event::StartDocument | Event::EndDocument => {}
+            Event::StartElement(name) => {
+                xml.close_start_tag(&mut out)?;
+                xml.open.push(OpenElement {
+                    local_name: name.local_name.to_string(),
+                    namespace: name.namespace.map(str::to_string),
+                    prefix: name.prefix.map(str::to_string),
+                    qualified: None,
+                    attributes: String::new(),
+                    bindings: xml.bindings.len(),
+                    in_start_tag: true,
+                });
+            }
+            Event::EndElement => {
+                let Some(mut element) = xml.open.pop() else {
+                    continue;
+                };
+                let name = xml.element_name(&mut element);
+                if element.in_start_tag {
+                    write!(out, "<{}{}/>", name, element.attributes)?;
+                } else {
+                    write!(out, "</{}>", name)?;
+                }
+                xml.bindings.truncate(element.bindings);
+            }
+            Event::NamespaceDeclaration(dec) => xml.declare(dec.prefix, dec.namespace),
+            Event::TypeAttribute(name) => {
+                let key = Name {
+                    local_name: "type",
+                    namespace: Some(XSI),
+                    prefix: Some("xsi"),
+                };
+                xml.attribute(&key, &Value::QName(name));
+            }
+            Event::Attribute(attr) => xml.attribute(&attr.key, &attr.value),
+            Event::Value(value) => {
+                let text = xml.value(&value);
+                xml.close_start_tag(&mut out)?;
+                out.write_all(escape(&text, false).as_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct XmlWriter {
+    /// (prefix, namespace) bindings in scope, innermost last
+    bindings: Vec<(String, String)>,
+    open: Vec<OpenElement>,
+    /// Number of prefixes generated so far
+    generated: usize,
+}
+
+struct OpenElement {
+    local_name: String,
+    namespace: Option<String>,
+    prefix: Option<String>,
+    /// Qualified name, once it's been decided
+    qualified: Option<String>,
+    /// Rendered namespace declarations and attributes
+    attributes: String,
+    /// Length of `XmlWriter::bindings` outside this element
+    bindings: usize,
+    in_start_tag: bool,
+}
+
+impl XmlWriter {
+    fn lookup(&self, prefix: &str) -> Option<&str> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|(p, _)| p == prefix)
+            .map(|(_, ns)| ns.as_str())
+    }
+
+    /// A prefix currently bound to `namespace`, if any
+    fn prefix_of(&self, namespace: &str, allow_default: bool) -> Option<String> {
+        self.bindings
+            .iter()
+            .rev()
+            .map(|(p, _)| p)
+            .filter(|p| allow_default || !p.is_empty())
+            .find(|p| self.lookup(p) == Some(namespace))
+            .cloned()
+    }
+
+    /// Bind `prefix` on the element whose start tag is being written
+    fn declare(&mut self, prefix: &str, namespace: &str) {
+        let Some(element) = self.open.last_mut().filter(|e| e.in_start_tag) else {
+            return;
+        };
+        element.attributes.push_str(" xmlns");
+        if !prefix.is_empty() {
+            element.attributes.push(':');
+            element.attributes.push_str(prefix);
+        }
+        element.attributes.push_str("=\"");
+        element.attributes.push_str(&escape(namespace, true));
+        element.attributes.push('"');
+        self.bindings.push((prefix.into(), namespace.into()));
+    }
+
+    /// Qualify a name that can't take the default namespace, declaring a prefix if needed
+    fn qualify(&mut self, name: &Name, allow_default: bool) -> String {
+        let Some(namespace) = name.namespace.filter(|ns| !ns.is_empty()) else {
+            return name.local_name.to_string();
+        };
+        let prefix = match name.prefix {
+            Some(p) if !p.is_empty() && self.lookup(p) == Some(namespace) => Some(p.to_string()),
+            _ => self.prefix_of(namespace, allow_default),
+        };
+        let prefix = match prefix {
+            Some(p) => p,
+            None if self.open.last().is_some_and(|e| e.in_start_tag) => {
+                let p = loop {
+                    let p = format!("ns{}", self.generated);
+                    self.generated += 1;
+                    if self.lookup(&p).is_none() {
+                        break p;
+                    }
+                };
+                self.declare(&p, namespace);
+                p
+            }
+            // Nowhere left to declare it
+            None => return name.local_name.to_string(),
+        };
+        if prefix.is_empty() {
+            name.local_name.to_string()
+        } else {
+            format!("{}:{}", prefix, name.local_name)
+        }
+    }
+
+    /// Decide the qualified name of `element`, declaring its namespace as the default if needed
+    fn element_name(&mut self, element: &mut OpenElement) -> String {
+        if let Some(name) = &element.qualified {
+            return name.clone();
+        }
+        let namespace = element.namespace.as_deref().unwrap_or_default();
+        let name = match element.prefix.as_deref() {
+            Some(p) if !p.is_empty() && self.lookup(p) == Some(namespace) => {
+                format!("{}:{}", p, element.local_name)
+            }
+            _ if self.lookup("").unwrap_or_default() == namespace => element.local_name.clone(),
+            _ => match self
+                .prefix_of(namespace, false)
+                .filter(|_| !namespace.is_empty())
+            {
+                Some(p) => format!("{}:{}", p, element.local_name),
+                None => {
+                    if element.in_start_tag {
+                        element.attributes.push_str(" xmlns=\"");
+                        element.attributes.push_str(&escape(namespace, true));
+                        element.attributes.push('"');
+                        self.bindings.push((String::new(), namespace.into()));
+                    }
+                    element.local_name.clone()
+                }
+            },
+        };
+        element.qualified = Some(name.clone());
+        name
+    }
+
+    /// Make sure the innermost open element's name is settled before its namespace bindings
+    /// are used for anything else
+    fn settle_element_name(&mut self) {
+        if let Some(mut element) = self.open.pop() {
+            self.element_name(&mut element);
+            self.open.push(element);
+        }
+    }
+
+    fn attribute(&mut self, key: &Name, value: &Value) {
+        self.settle_element_name();
+        let key = match key.namespace {
+            Some(XML) => format!("xml:{}", key.local_name),
+            _ => self.qualify(key, false),
+        };
+        let value = self.value(value);
+        if let Some(element) = self.open.last_mut().filter(|e| e.in_start_tag) {
+            element.attributes.push(' ');
+            element.attributes.push_str(&key);
+            element.attributes.push_str("=\"");
+            element.attributes.push_str(&escape(&value, true));
+            element.attributes.push('"');
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match value {
+            Value::QName(name) => {
+                self.settle_element_name();
+                self.qualify(name, true)
+            }
+            Value::List(items) => items
+                .iter()
+                .map(|item| self.value(item))
+                .collect::<Vec<_>>()
+                .join(" "),
+            other => other.to_string(),
+        }
+    }
+
+    fn close_start_tag(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        if let Some(mut element) = self.open.pop() {
+            if element.in_start_tag {
+                let name = self.element_name(&mut element);
+                write!(out, "<{}{}>", name, element.attributes)?;
+                element.in_start_tag = false;
+            }
+            self.open.push(element);
+        }
+        Ok(())
+    }
+}
+
+/// Escape `text` for character content, or for a double-quoted attribute value
+fn escape(text: &str, attribute: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#13;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '\t' if attribute => out.push_str("&#9;"),
+            '\n' if attribute => out.push_str("&#10;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn schemaless_encode() {
+    use crate::config::Options;
+
+    let options = Options::default().strict(true);
+    let header = Header::with_options(options).has_cookie(true);
+    let xml = r#"<?xml version="1.0"?>
+        <!-- dropped -->
+        <MultipleXSDsTest xmlns="http://www.ltu.se/EISLAB/schema-test">This is an example of serializing EXI streams using EXIP low level API</MultipleXSDsTest>"#;
+    let encoded = encode(xml.as_bytes(), header.clone(), None).unwrap();
+
+    let mut writer = Writer::new(header, None).unwrap();
+    writer
+        .document(|d| {
+            d.element(
+                Name {
+                    local_name: "MultipleXSDsTest",
+                    namespace: Some("http://www.ltu.se/EISLAB/schema-test"),
+                    prefix: None,
+                },
+                |e| {
+                    e.text("This is an example of serializing EXI streams using EXIP low level API")
+                },
+            )
+        })
+        .unwrap();
+    assert_eq!(encoded, writer.get());
+}
+
+#[test]
+fn schema_encode() {
+    use crate::config::Options;
+    use crate::testing::{example_schema, FULL_DOCUMENT};
+
+    let options = Options::default().strict(true);
+    let header = Header::with_options(options).has_cookie(true);
+    let schema = example_schema(None);
+    // The document written event by event in writer::full_write
+    let xml = r#"<MultipleXSDsTest xmlns="http://www.ltu.se/EISLAB/schema-test" xmlns:n="http://www.ltu.se/EISLAB/nested-xsd">
+  <EXIPEncoder version="0.2" testByte="55">This is an example of serializing EXI streams using EXIP low level API</EXIPEncoder>
+  <description>This is a test of processing XML schemes with multiple XSD files</description>
+  <n:testSetup goal="Verify that the implementation works!">Simple test element with single attribute</n:testSetup>
+  <type-test id="1001">
+    <n:bool>true</n:bool>
+  </type-test>
+  <extendedTypeTest>
+    <byteTest xmlns="">11</byteTest>
+    <dateTimeTest xmlns="">2012-07-31T13:33:55.000839</dateTimeTest>
+    <binaryTest xmlns="">Am0vpSDyYZzuDw==</binaryTest>
+    <enumTest xmlns="">hej</enumTest>
+  </extendedTypeTest>
+</MultipleXSDsTest>"#;
+    let encoded = encode(xml.as_bytes(), header, Some(schema)).unwrap();
+    assert_eq!(encoded, FULL_DOCUMENT);
+}
+
+#[test]
+fn invalid_lexical() {
+    use crate::testing::example_schema;
+    use crate::{config::Options, error::ErrorKind};
+
+    let options = Options::default().strict(true);
+    let schema = example_schema(None);
+    let xml = r#"<MultipleXSDsTest xmlns="http://www.ltu.se/EISLAB/schema-test">
+  <EXIPEncoder version="0.2" testByte="many">text</EXIPEncoder>
+</MultipleXSDsTest>"#;
+    let Err(XmlError::Exi(e)) = encode(xml.as_bytes(), Header::with_options(options), Some(schema))
+    else {
+        panic!("expected an EXI error");
+    };
+    assert!(matches!(e.kind(), ErrorKind::InvalidLexical(v, _) if v == "many"));
+    assert_eq!(e.path(), Some("/MultipleXSDsTest/EXIPEncoder/@testByte"));
+}
+
+#[test]
+fn malformed_xml() {
+    let xml = "<a><b></a>";
+    assert!(matches!(
+        encode(xml.as_bytes(), Header::default(), None),
+        Err(XmlError::Syntax { .. })
+    ));
+}
+
+#[test]
+fn whitespace() {
+    let xml = "<root>\n  <a> </a>\n  <b>\t</b>\n</root>";
+    let exi = encode(xml.as_bytes(), Header::default(), None).unwrap();
+    let reader = Reader::new(exi, None, None).unwrap();
+    assert_eq!(
+        decode(reader).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><root><a> </a><b>\t</b></root>"
+    );
+}
+
+#[test]
+fn preserved_comments() {
+    use crate::config::Options;
+
+    let xml = "<root><!-- note --></root>";
+    let options = Options::default().preserve_comments(true);
+    assert!(matches!(
+        encode(xml.as_bytes(), Header::with_options(options), None),
+        Err(XmlError::Unsupported("comments"))
+    ));
+    assert!(encode(xml.as_bytes(), Header::default(), None).is_ok());
+}
+
+#[test]
+fn schemaless_decode() {
+    let xml = r#"<a:root xmlns:a="urn:a" id="1"><child>x &lt; y &amp; "z"</child><empty note="a&#10;b"/></a:root>"#;
+    let exi = encode(xml.as_bytes(), Header::default(), None).unwrap();
+    let reader = Reader::new(exi, None, None).unwrap();
+    assert_eq!(
+        decode(reader).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?><root xmlns="urn:a" id="1"><child xmlns="">x &lt; y &amp; "z"</child><empty xmlns="" note="a&#10;b"/></root>"#
+    );
+}
+
+#[test]
+fn preserved_prefixes_decode() {
+    use crate::config::Options;
+
+    let options = Options::default().preserve_prefixes(true);
+    let xml = r#"<a:root xmlns:a="urn:a"><a:child>text</a:child></a:root>"#;
+    let exi = encode(xml.as_bytes(), Header::with_options(options), None).unwrap();
+    let reader = Reader::new(exi, None, None).unwrap();
+    assert_eq!(
+        decode(reader).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?><a:root xmlns:a="urn:a"><a:child>text</a:child></a:root>"#
+    );
+}
+
+#[test]
+fn typed_decode() {
+    use crate::config::Options;
+    use crate::testing::example_schema;
+
+    let schema = || example_schema(None);
+    let options = Options::default().strict(true);
+    let xml = r#"<MultipleXSDsTest xmlns="http://www.ltu.se/EISLAB/schema-test" xmlns:n="http://www.ltu.se/EISLAB/nested-xsd">
+  <EXIPEncoder version="0.2" testByte="55">text</EXIPEncoder>
+  <description>A &amp; B</description>
+  <n:testSetup goal="goal">setup</n:testSetup>
+  <type-test id="1001"><n:bool>true</n:bool></type-test>
+  <extendedTypeTest>
+    <byteTest xmlns="">11</byteTest>
+    <dateTimeTest xmlns="">2012-07-31T13:33:55.000839</dateTimeTest>
+    <binaryTest xmlns="">Am0vpSDyYZzuDw==</binaryTest>
+    <enumTest xmlns="">hej</enumTest>
+  </extendedTypeTest>
+</MultipleXSDsTest>"#;
+    let exi = encode(
+        xml.as_bytes(),
+        Header::with_options(options),
+        Some(schema()),
+    )
+    .unwrap();
+    let reader = Reader::new(exi, Some(schema()), None).unwrap();
+    let decoded = decode(reader).unwrap();
+    for expected in [
+        r#"<MultipleXSDsTest xmlns="http://www.ltu.se/EISLAB/schema-test"><EXIPEncoder testByte="55" version="0.2">text</EXIPEncoder>"#,
+        "<description>A &amp; B</description>",
+        r#"<testSetup xmlns="http://www.ltu.se/EISLAB/nested-xsd" goal="goal">setup</testSetup>"#,
+        r#"<type-test id="1001"><bool xmlns="http://www.ltu.se/EISLAB/nested-xsd">true</bool></type-test>"#,
+        r#"<byteTest xmlns="">11</byteTest>"#,
+        "2012-07-31T13:33:55.000839</dateTimeTest>",
+        ">Am0vpSDyYZzuDw==</binaryTest>",
+        ">hej</enumTest>",
+    ] {
+        assert!(
+            decoded.contains(expected),
+            "{} not in {}",
+            expected,
+            decoded
+        );
+    }
+}